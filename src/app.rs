@@ -1,82 +1,138 @@
 use yew::prelude::*;
-use yew::html::ChildrenRenderer;
 use stylist::yew::Global;
 use stylist::css;
-use std::rc::{Rc,Weak};
-use std::cell::Cell;
-use crate::utils::scope_channel::{Receiver,scope_channel};
+use crate::utils::bus::{Bus,Subscription};
+use gloo_events::EventListener;
+use std::rc::Rc;
+pub use route::Route;
+pub use calculator::layout::Layout;
+pub use calculator::EntryMode;
+use crate::calc_unit::formula::Formula;
+pub use crate::calc_unit::{StackModel,Backend,DecimalContext,Rounding,IntContext,Overflow,Format,FractionStyle,ComplexStyle,Base};
 #[allow(unused_imports)]
 use log::{trace, debug, info, warn, error};
 
 mod calculator;
 mod logging_tray;
+mod settings;
+mod help;
+mod plot;
+mod route;
 
 pub enum AppMsg {
-    ShowCalculator,
-    ShowLoggingTray,
-    LogMsg(String),
-    ChangeColorTheme(ColorTheme)
+    RouteChanged(Route),
+    ChangeConfig(SharedConfig)
 }
 
-#[derive(Clone,Copy)]
+#[derive(Clone,Copy,PartialEq)]
 pub enum ColorTheme {
     Dark,
     Light
 }
 
-#[derive(Clone,Copy)]
-pub struct SharedConfig {
-    color_theme: ColorTheme
-}
+/// Bus event: append a message to the logging tray.
+#[derive(Clone)]
+pub struct Log(pub String);
 
-impl Default for SharedConfig {
-    fn default() -> Self {
-        SharedConfig {
-            color_theme: ColorTheme::Light
-        }
-    }
+/// Bus event: add a series to the plot.
+#[derive(Clone)]
+pub enum PlotSeries {
+    /// A formula of x, with how it reads.
+    Formula(Formula,String),
+    /// (x, y) points.
+    Points(Vec<(f64,f64)>)
 }
 
-pub struct SharedConfigHandle(Weak<Cell<SharedConfig>>);
+/// Bus event: push a point picked on the plot, y in Y and x in X.
+#[derive(Clone)]
+pub struct PushPoint(pub f64,pub f64);
 
-impl Clone for SharedConfigHandle {
-    fn clone(&self) -> Self {
-        SharedConfigHandle(Weak::clone(&self.0))
-    }
-}
+/// Bus event: replace the shared configuration.
+#[derive(Clone)]
+pub struct SetConfig(pub SharedConfig);
 
-impl PartialEq for SharedConfigHandle {
-    fn eq(&self, _other: &Self) -> bool {
-        true
-    }
+/// Configuration shared by every screen. It is handed down through a
+/// `ContextProvider`, so components subscribe with `ctx.link().context` and
+/// decide themselves whether a change touches the fields they render.
+#[derive(Clone,PartialEq)]
+pub struct SharedConfig {
+    pub color_theme: ColorTheme,
+    pub keypad_layout: Rc<Layout>,
+    pub entry_mode: EntryMode,
+    pub stack_model: StackModel,
+    pub backend: Backend,
+    /// Decimal settings, kept while another backend is selected.
+    pub decimal: DecimalContext,
+    /// Programmer word settings, kept while another backend is selected.
+    pub integer: IntContext,
+    pub number_format: Format
 }
 
-
-impl SharedConfigHandle {
-    fn get(&self) -> SharedConfig {
-        (self.0).upgrade().unwrap().get()
+impl Default for SharedConfig {
+    fn default() -> Self {
+        SharedConfig {
+            color_theme: ColorTheme::Light,
+            keypad_layout: Rc::new(Layout::default()),
+            entry_mode: EntryMode::AutoEnter,
+            stack_model: StackModel::Unbounded,
+            backend: Backend::Float,
+            decimal: DecimalContext::default(),
+            integer: IntContext::default(),
+            number_format: Format::default()
+        }
     }
 }
 
 pub struct App {
-    children: Html,
-    calculator_recv: Receiver<calculator::Calculator>,
-    logging_tray_recv: Receiver<logging_tray::LoggingTray>,
-    shared_config: Rc<Cell<SharedConfig>>
+    bus: Bus,
+    route: Route,
+    shared_config: SharedConfig,
+    _subscriptions: Vec<Subscription>,
+    _hash_listener: Option<EventListener>
 }
 
 impl Component for App {
     type Message = AppMsg;
     type Properties = ();
-    fn create(_ctx: &Context<Self>) -> Self {
+    fn create(ctx: &Context<Self>) -> Self {
+        let bus = Bus::new();
+        let _subscriptions = vec![
+            bus.subscribe(ctx.link().callback(|SetConfig(c)| AppMsg::ChangeConfig(c)))
+        ];
+        let _hash_listener = web_sys::window().map(|w| {
+            let link = ctx.link().clone();
+            EventListener::new(&w,"hashchange",move |_| link.send_message(AppMsg::RouteChanged(Route::current())))
+        });
+        App {
+            bus,
+            route: Route::current(),
+            shared_config: SharedConfig::default(),
+            _subscriptions,
+            _hash_listener
+        }
+    }
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            AppMsg::RouteChanged(route) => {
+                let changed = self.route != route;
+                self.route = route;
+                changed
+            },
+            AppMsg::ChangeConfig(new_c) => {
+                let changed = self.shared_config != new_c;
+                self.shared_config = new_c;
+                changed
+            }
+        }
+    }
+    fn view(&self, _ctx: &Context<Self>) -> Html {
         use calculator::Calculator;
         use logging_tray::LoggingTray;
-        let shared_config = Rc::new(Cell::new(SharedConfig::default()));
-        let shared_config_handle = SharedConfigHandle(Rc::downgrade(&shared_config));
-        let (calculator_snd, calculator_recv) = scope_channel();
-        let (logging_tray_snd, logging_tray_recv) = scope_channel();
-        let children = html!{
-            < >
+        use settings::Settings;
+        use help::Help;
+        use plot::Plot;
+        html!{
+            <ContextProvider<SharedConfig> context={ self.shared_config.clone() }>
                 <Global css={{ css!{
                     body, html {
                         width: 100%;
@@ -94,36 +150,17 @@ impl Component for App {
                         border: 0;
                     }
                 }}}></Global>
-                <Calculator visible=true scope_snd={ calculator_snd } config={ shared_config_handle.clone() }></Calculator>
-                <LoggingTray visible=false scope_snd={ logging_tray_snd } config={ shared_config_handle }></LoggingTray>
-            </>
-        };
-        App { children, calculator_recv, logging_tray_recv, shared_config }
-    }
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        match msg {
-            AppMsg::ShowCalculator | AppMsg::ShowLoggingTray => {
-                let mut calculator_msg = calculator::CalculatorMsg::Hide;
-                let mut logging_tray_msg = logging_tray::LoggingTrayMsg::Hide;
-                match msg {
-                    AppMsg::ShowCalculator => calculator_msg = calculator::CalculatorMsg::Show,
-                    AppMsg::ShowLoggingTray => logging_tray_msg = logging_tray::LoggingTrayMsg::Show,
-                    _ => unreachable!()
-                }
-                self.calculator_recv.recv().send_message(calculator_msg);
-                self.logging_tray_recv.recv().send_message(logging_tray_msg);    
-            },
-            AppMsg::LogMsg(s) => self.logging_tray_recv.recv().send_message(logging_tray::LoggingTrayMsg::LogMsg(s)),
-            AppMsg::ChangeColorTheme(new_c) => { self.shared_config.update(|mut c| { c.color_theme = new_c; c}); }
-        }   
-        true
-    }
-    fn view(&self, _ctx: &Context<Self>) -> Html {
-        let rendered_children = ChildrenRenderer::new(vec![self.children.clone()]);
-        html!(
-            < >
-                {rendered_children}
-            </>
-        )
+                // Every screen stays mounted and renders nothing while
+                // hidden. The calculator's stack, undo history, statistics,
+                // TVM registers and formula, the log and the plot's series
+                // all live in their components, and the log and plot must
+                // keep taking bus events while another screen is shown.
+                <Calculator visible={ self.route == Route::Calc } bus={ self.bus.clone() }></Calculator>
+                <LoggingTray visible={ self.route == Route::Log } bus={ self.bus.clone() }></LoggingTray>
+                <Settings visible={ self.route == Route::Settings } bus={ self.bus.clone() }></Settings>
+                <Plot visible={ self.route == Route::Plot } bus={ self.bus.clone() }></Plot>
+                <Help visible={ self.route == Route::Help }></Help>
+            </ContextProvider<SharedConfig>>
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,392 +1,462 @@
 use yew::prelude::*;
 use stylist::css;
-use yew::html::Scope;
-use crate::utils::scope_channel::Sender;
+use crate::utils::bus::{Bus,Subscription};
+use yew::context::ContextHandle;
+use super::{Route,Log,SetConfig,SharedConfig,StackModel,Format,Base,PlotSeries,PushPoint};
 use crate::utils::Ignore;
 #[allow(unused_imports)]
 use log::{trace, debug, info, warn, error};
-use std::str::FromStr;
-use ambassador::{delegatable_trait,Delegate};
+use gloo_events::EventListener;
+use std::collections::BTreeSet;
+use wasm_bindgen::JsCast;
+use layout::KeyAction;
+use crate::calc_unit::ops::{CommOrOp,Command,Native,UnitOp,Arity};
+use crate::calc_unit::units::{self,UnitRef};
+use crate::calc_unit::{Value,CalcError,CalcResult};
+use crate::calc_unit::formula::{Formula,Step};
+use matrix_editor::MatrixEditor;
 
-#[delegatable_trait]
-pub trait ExtractCommand {
-    fn command_or_op(self) -> rpncalc::CommandOrOp;
-}
+pub mod layout;
+pub mod registry;
+mod matrix_editor;
 
-#[derive(Delegate)]
-#[delegate(ExtractCommand)]
+#[derive(Clone,Copy,PartialEq)]
 pub struct CommOrOpWrapper(CommOrOp);
 
-#[derive(Delegate)]
-#[delegate(ExtractCommand)]
-enum CommOrOp {
-    Command(Command),
-    Op(Op)
-}
-
-enum Command {
-    Drop,
-    Dup,
-    Swap,
-    Rev
-}
-
-impl ExtractCommand for Command {
-    fn command_or_op(self) -> rpncalc::CommandOrOp {
-        rpncalc::CommandOrOp::Command(match self {
-            Command::Drop => rpncalc::CommandEnum::Drop(rpncalc::Drop::Some(1)),
-            Command::Dup  => rpncalc::CommandEnum::Dup(rpncalc::Dup(1)),
-            Command::Swap => rpncalc::CommandEnum::Swap(rpncalc::Swap::LastTwo),
-            Command::Rev  => rpncalc::CommandEnum::Reverse(rpncalc::Reverse)
-        })
-    }
-}
-
-#[derive(Delegate)]
-#[delegate(ExtractCommand)]
-enum Op {
-    Arith(Arith),
-    Constant(Constant),
-    Exp2(Exp2),
-    Exp(Exp),
-    Trig(Trig)
-}
-
-enum Constant {
-    Pi,
-    E,
-    Inf
-}
-
-impl ExtractCommand for Constant {
-    fn command_or_op(self) -> rpncalc::CommandOrOp {
-        match self {
-            Constant::E   => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Constants(rpncalc::ops::Constants::E  )),
-            Constant::Pi  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Constants(rpncalc::ops::Constants::PI )),
-            Constant::Inf => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Constants(rpncalc::ops::Constants::Inf)),
-        }
-    }
-}
-
-enum Arith {
-    Add,
-    Sub,
-    Mul,
-    Div
-}
-
-impl ExtractCommand for Arith {
-    fn command_or_op(self) -> rpncalc::CommandOrOp {
-        rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Arith(match self {
-            Arith::Add => rpncalc::ops::Arith::Add,
-            Arith::Sub => rpncalc::ops::Arith::Sub,
-            Arith::Mul => rpncalc::ops::Arith::Mul,
-            Arith::Div => rpncalc::ops::Arith::Div
-        }))
-    }
-}
-
-enum Exp2 {
-    Pow,
-    LogN,
-    RootN
-}
-
-impl ExtractCommand for Exp2 {
-    fn command_or_op(self) -> rpncalc::CommandOrOp {
-        match self {
-            Exp2::Pow => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsBinary(rpncalc::ops::ExponentialsBinary::Pow)),
-            Exp2::LogN => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsBinary(rpncalc::ops::ExponentialsBinary::LogN)),
-            Exp2::RootN => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsBinary(rpncalc::ops::ExponentialsBinary::RootN))
-        }
-    }
-}
-
-enum Exp {
-    Log10,
-    Log2,
-    LogE,
-    Root2
-}
-
-impl ExtractCommand for Exp {
-    fn command_or_op(self) -> rpncalc::CommandOrOp {
-        match self {
-            Exp::Log10 => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::Log10)),
-            Exp::Log2  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::Log2 )),
-            Exp::LogE  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::LogE )),
-            Exp::Root2 => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::Root2))
-        }
-    }
-}
-
-enum Trig {
-    Sin,
-    Cos,
-    Tan,
-    ASin,
-    ACos,
-    ATan
-}
-
-impl ExtractCommand for Trig {
-    fn command_or_op(self) -> rpncalc::CommandOrOp {
-        match self {
-            Trig::Sin  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::Sin )),
-            Trig::Cos  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::Cos )),
-            Trig::Tan  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::Tan )),
-            Trig::ASin => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::ASin)),
-            Trig::ACos => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::ACos)),
-            Trig::ATan => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::ATan))
-        }
-    }
+/// How the number being typed interacts with the stack.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum EntryMode {
+    /// Operators push a non-empty entry before running.
+    AutoEnter,
+    /// HP-style: ENTER copies X and disables stack lift, so the next number
+    /// typed replaces the copy.
+    StackLift,
+    /// Operators ignore the entry; only Ins pushes it.
+    Manual
 }
 
 pub enum CalculatorMsg {
-    Show,
-    Hide,
     DigitInput(u8),
     InsNum,
     Backspace,
     Dot,
-    CommOrOp(CommOrOpWrapper)
+    Imag,
+    Polar,
+    SetBase(Base),
+    /// Shows or hides the unit picker in place of the keypad.
+    UnitPicker(bool),
+    UnitPrefix(u8),
+    Unit(UnitRef),
+    /// Opens the matrix editor, optionally on a copy of a stack value.
+    EditMatrix(Option<Value>),
+    /// The matrix editor closed, with a value to push unless cancelled.
+    MatrixDone(Option<Value>),
+    /// Shows a stack level in full, by its distance from X.
+    Inspect(Option<usize>),
+    /// Shows or hides the statistics data list in place of the stack.
+    StatData(bool),
+    /// New text for the x (false) or y (true) of a statistics point.
+    EditStat(usize,bool,String),
+    DeleteStat(usize),
+    /// Shows or hides the TVM registers and amortization schedule in place
+    /// of the stack.
+    Amortization(bool),
+    /// Selects or deselects a payment of the amortization schedule.
+    SelectPayment(usize),
+    /// Pushes the interest and principal summed over the selected payments.
+    PushPayments,
+    CommOrOp(CommOrOpWrapper),
+    Repeat,
+    Undo,
+    /// Starts or stops recording the formula.
+    Record,
+    /// Sends the recorded formula to the plot.
+    PlotFormula,
+    /// Sends the stack to the plot as points.
+    PlotStack,
+    /// Pushes a point picked on the plot.
+    PushPoint(f64,f64),
+    KeyDown(String),
+    ConfigChanged(SharedConfig)
 }
 
 #[derive(PartialEq,Properties)]
 pub struct CalculatorProp {
-    pub scope_snd: Sender<Calculator>,
-    pub config: super::SharedConfigHandle,
+    pub bus: Bus,
     pub visible: bool
 }
 
 pub struct Calculator {
-    visible: bool,
     display: String,
+    /// Whether `display` holds something typed rather than the "0" placeholder.
+    entering: bool,
+    /// Set by an HP-style ENTER; the next pushed entry overwrites X.
+    lift_disabled: bool,
+    /// Text of the entry pushed since the last op, if any.
+    last_entry: Option<String>,
+    /// The last op run, with how many levels it took when it works on the
+    /// whole stack, and the constant operand it was given when it was a
+    /// binary op applied straight after entering a number.
+    last_op: Option<(CommOrOpWrapper,Option<usize>,Option<String>)>,
     stack_affected: bool,
-    calc_unit: crate::calc_unit::CalcUnit
+    unit_picker: bool,
+    /// Prefix selected in the unit picker, as an index into `units::PREFIXES`.
+    unit_prefix: u8,
+    /// Open matrix editor and the value it started from.
+    matrix_editor: Option<Option<Value>>,
+    inspecting: Option<usize>,
+    stat_data: bool,
+    /// Open amortization view and the payments selected in it.
+    amortization: Option<BTreeSet<usize>>,
+    calc_unit: crate::calc_unit::CalcUnit,
+    config: SharedConfig,
+    _config_handle: ContextHandle<SharedConfig>,
+    _subscriptions: Vec<Subscription>,
+    _key_listener: Option<EventListener>
+}
+
+impl Calculator {
+    /// Classic register names for the bottom four levels of a fixed stack.
+    fn register_name(level: usize) -> String {
+        match level {
+            0 => "X".to_string(),
+            1 => "Y".to_string(),
+            2 => "Z".to_string(),
+            3 => "T".to_string(),
+            n => n.to_string()
+        }
+    }
+    /// Reads typed text in the display base.
+    fn parse_number(&self, text: &str) -> CalcResult<Value> {
+        match self.config.number_format.base {
+            Base::Dec => self.calc_unit.parse(text),
+            base => match i128::from_str_radix(text,base.radix()) {
+                Ok(n) => self.calc_unit.parse(&n.to_string()),
+                Err(_) => Err(CalcError::InvalidNumber(text.to_string()))
+            }
+        }
+    }
+    /// Logs the error of `res`, if any, and tells whether there was none.
+    fn check(ctx: &Context<Self>, res: CalcResult<()>) -> bool {
+        if let Err(e) = &res {
+            ctx.props().bus.publish(Log(format!("{e}\n")));
+        }
+        res.is_ok()
+    }
+    /// The configured number format, with negative integers shown at the
+    /// programmer word size.
+    fn number_format(&self) -> Format {
+        Format { word_bits: self.config.integer.bits, ..self.config.number_format }
+    }
+    /// Pushes the entry line onto the stack and clears it. An entry that
+    /// cannot be read stays on the entry line to be corrected.
+    fn push_entry(&mut self) -> CalcResult<()> {
+        let v = self.parse_number(&self.display)?;
+        let text = std::mem::replace(&mut self.display,"0".to_string());
+        self.entering = false;
+        self.calc_unit.push(v);
+        self.last_entry = Some(text);
+        Ok(())
+    }
+    /// Pushes a typed entry, first dropping the copy left in X by an HP-style ENTER.
+    fn lift_entry(&mut self) -> CalcResult<()> {
+        if self.entering {
+            if self.lift_disabled {
+                // Read first, so an entry that cannot be read leaves the copy.
+                self.parse_number(&self.display)?;
+                self.calc_unit.apply(CommOrOp::Command(Command::Drop)).ignore();
+            }
+            self.push_entry()?;
+        }
+        Ok(())
+    }
+    /// Lets an op about to run take the entry line, as the entry mode
+    /// dictates. The op must not run when this fails.
+    fn consume_entry(&mut self) -> CalcResult<()> {
+        match self.config.entry_mode {
+            EntryMode::AutoEnter => if self.entering { self.push_entry()? },
+            EntryMode::StackLift => self.lift_entry()?,
+            EntryMode::Manual => ()
+        }
+        self.lift_disabled = false;
+        Ok(())
+    }
+    /// Lets an op about to run take the entry line: as how many levels to
+    /// work on for ops on the whole stack, as a number for anything else.
+    fn entry_for(&mut self, op: CommOrOp) -> Result<Option<usize>,String> {
+        if op.arity() != Arity::Whole || !self.entering {
+            self.consume_entry().map_err(|e| e.to_string())?;
+            return Ok(None);
+        }
+        let levels = usize::from_str_radix(&self.display,self.config.number_format.base.radix())
+            .map_err(|_| format!("'{}' is not a number of stack levels",self.display))?;
+        self.display = "0".to_string();
+        self.entering = false;
+        self.lift_disabled = false;
+        Ok(Some(levels))
+    }
+    /// Starts a user step on the stack, which undo reverts as a whole.
+    fn begin_step(&mut self) {
+        self.stack_affected = true;
+        self.calc_unit.checkpoint();
+    }
+    fn execute(&mut self, ctx: &Context<Self>, op: CommOrOp, top: Option<usize>) {
+        let (stdout,res) = self.calc_unit.run_op_top(op,top);
+        let mut out = String::new();
+        out += &std::string::String::from_utf8_lossy(&stdout);
+        out.push('\n');
+        match res {
+            Ok(Some(s)) => out += &s,
+            Err(e) => out += &e.to_string(),
+            _ => ()
+        }
+        out.push('\n');
+        ctx.props().bus.publish(Log(out));
+    }
+    /// Whether the entry line already has an imaginary part or an angle.
+    fn entry_is_complex(&self) -> bool {
+        self.display.contains(|c| c == 'i' || c == '∠')
+    }
+    fn unit_picker(&self, ctx: &Context<Self>) -> Html {
+        let prefixes = units::PREFIXES.iter().enumerate().map(|(i,p)| {
+            let i = i as u8;
+            let label = if p.symbol.is_empty() { "—" } else { p.symbol };
+            html!{<button disabled={ i == self.unit_prefix } onclick={ ctx.link().callback(move |_| CalculatorMsg::UnitPrefix(i)) }> { label } </button>}
+        }).collect::<Html>();
+        let prefix = units::PREFIXES[self.unit_prefix as usize].symbol;
+        let unit_keys = units::UNITS.iter().enumerate().map(|(i,def)| {
+            let unit = UnitRef { unit: i as u8, prefix: self.unit_prefix };
+            html!{
+                <button title={ def.name } disabled={ self.unit_prefix != 0 && !def.prefixable } onclick={ ctx.link().callback(move |_| CalculatorMsg::Unit(unit)) }>
+                    { format!("{prefix}{}",def.symbol) }
+                </button>
+            }
+        }).collect::<Html>();
+        html!{
+            <div class={{css!{grid-area: d; overflow-y: scroll;}}}>
+                <div> { prefixes } </div>
+                <div class={{css!{display: grid; width: 100%; grid-template-columns: repeat(6, 1fr);}}}> { unit_keys } </div>
+                <button onclick={ ctx.link().callback(|_| CalculatorMsg::UnitPicker(false)) }> { "Close" } </button>
+            </div>
+        }
+    }
+    /// A stack value in full: arrays as a table, anything else as text.
+    fn inspector(&self, ctx: &Context<Self>, level: usize) -> Html {
+        let stack = self.calc_unit.get_stack();
+        let value = &stack[stack.len() - 1 - level];
+        let table = |rows: Vec<&[f64]>| html!{
+            <table>
+                { rows.into_iter().map(|row| html!{
+                    <tr> { row.iter().map(|x| html!{ <td> { rpncalc::format_num(*x).to_string() } </td> }).collect::<Html>() } </tr>
+                }).collect::<Html>() }
+            </table>
+        };
+        let (body,editable) = match value {
+            Value::Vector(v) => (table(vec![&v[..]]),true),
+            Value::Matrix(m) => (table((0..m.rows).map(|r| m.row(r)).collect()),true),
+            Value::BigInt(n) => (html!{
+                <>
+                    <p class={{css!{overflow-wrap: anywhere;}}}> { value.format(&self.number_format()) } </p>
+                    <p> { format!("{} digits",n.magnitude().to_string().len()) } </p>
+                </>
+            },false),
+            v => (html!{ <p class={{css!{overflow-wrap: anywhere;}}}> { v.format(&self.number_format()) } </p> },false)
+        };
+        let copy = value.clone();
+        html!{
+            <div class={{css!{grid-area: b; overflow: scroll;}}}>
+                { body }
+                <button onclick={ ctx.link().callback(|_| CalculatorMsg::Inspect(None)) }> { "Close" } </button>
+                <button disabled={ !editable } onclick={ ctx.link().callback(move |_| CalculatorMsg::EditMatrix(Some(copy.clone()))) }> { "Edit a Copy" } </button>
+            </div>
+        }
+    }
+    fn stat_data_view(&self, ctx: &Context<Self>) -> Html {
+        let cell = |i: usize, is_y: bool, value: Option<f64>| html!{
+            <td>
+                <input class={{css!{width: 100%; box-sizing: border-box;}}} inputmode="decimal"
+                    value={ value.map(|v| rpncalc::format_num(v).to_string()).unwrap_or_default() }
+                    onchange={ ctx.link().callback(move |e: Event| CalculatorMsg::EditStat(i,is_y,e.target_unchecked_into::<web_sys::HtmlInputElement>().value())) }/>
+            </td>
+        };
+        let rows = self.calc_unit.stat_points().iter().enumerate().map(|(i,p)| html!{
+            <tr>
+                <td> { (i + 1).to_string() } </td>
+                { cell(i,false,Some(p.x)) }
+                { cell(i,true,p.y) }
+                <td> <button onclick={ ctx.link().callback(move |_| CalculatorMsg::DeleteStat(i)) }> { "×" } </button> </td>
+            </tr>
+        }).collect::<Html>();
+        html!{
+            <div class={{css!{grid-area: b; overflow: scroll;}}}>
+                <table>
+                    <tr> <th> { "#" } </th> <th> { "x" } </th> <th> { "y" } </th> <th></th> </tr>
+                    { rows }
+                </table>
+                <button onclick={ ctx.link().callback(|_| CalculatorMsg::StatData(false)) }> { "Close" } </button>
+            </div>
+        }
+    }
+    fn amortization_view(&self, ctx: &Context<Self>, selected: &BTreeSet<usize>) -> Html {
+        let tvm = self.calc_unit.tvm();
+        let num = |x: f64| rpncalc::format_num(x).to_string();
+        let registers = [("N",tvm.n),("I/YR",tvm.i_yr),("PV",tvm.pv),("PMT",tvm.pmt),("FV",tvm.fv),("P/YR",tvm.p_yr)]
+            .into_iter()
+            .map(|(name,x)| html!{ <tr> <td> { name } </td> <td> { num(x) } </td> </tr> })
+            .collect::<Html>();
+        let mode = if tvm.begin { "Payments at the start of each period" } else { "Payments at the end of each period" };
+        let rows = tvm.schedule().into_iter().map(|row| {
+            let period = row.period;
+            let class = if selected.contains(&period) { css!{background-color: gray;} } else { css!{} };
+            html!{
+                <tr class={ class } onclick={ ctx.link().callback(move |_| CalculatorMsg::SelectPayment(period)) }>
+                    <td> { period.to_string() } </td>
+                    <td> { num(row.interest) } </td>
+                    <td> { num(row.principal) } </td>
+                    <td> { num(row.balance) } </td>
+                </tr>
+            }
+        }).collect::<Html>();
+        html!{
+            <div class={{css!{grid-area: b; overflow: scroll;}}}>
+                <table> { registers } </table>
+                <p> { mode } </p>
+                <table>
+                    <tr> <th> { "#" } </th> <th> { "Interest" } </th> <th> { "Principal" } </th> <th> { "Balance" } </th> </tr>
+                    { rows }
+                </table>
+                <button disabled={ selected.is_empty() } onclick={ ctx.link().callback(|_| CalculatorMsg::PushPayments) }> { "Push Interest and Principal" } </button>
+                <button onclick={ ctx.link().callback(|_| CalculatorMsg::Amortization(false)) }> { "Close" } </button>
+            </div>
+        }
+    }
+    fn record_label(&self) -> String {
+        match self.calc_unit.recording() {
+            true => "■ Rec".to_string(),
+            false => "Rec".to_string()
+        }
+    }
+    /// How an op reads: its key label, or the symbol of the unit it
+    /// converts to.
+    fn op_label(op: CommOrOp) -> String {
+        match (op,registry::by_op(CommOrOpWrapper(op))) {
+            (_,Some(def)) => def.label.to_string(),
+            (CommOrOp::Native(Native::Units(UnitOp::To(unit))),None) => unit.unit().symbol,
+            (_,None) => "?".to_string()
+        }
+    }
+    /// How a recorded step reads in the log.
+    fn step_label(&self, step: &Step) -> String {
+        match step {
+            Step::Push(v) => v.format(&self.number_format()),
+            Step::Run(op,None) => Self::op_label(*op),
+            Step::Run(op,Some(top)) => format!("{top} {}",Self::op_label(*op))
+        }
+    }
+    fn formula_text(&self, formula: &Formula) -> String {
+        let steps = formula.steps.iter().map(|step| self.step_label(step)).collect::<Vec<_>>();
+        format!("f(x) = x {}",steps.join(" "))
+    }
+    /// The stack as points to plot: the rows of a two-column matrix in X, or
+    /// else every real value against its level counted from the bottom.
+    fn stack_points(&self) -> Vec<(f64,f64)> {
+        let stack = self.calc_unit.get_stack();
+        match stack.last() {
+            Some(Value::Matrix(m)) if m.cols == 2 => (0..m.rows).map(|r| (m.row(r)[0],m.row(r)[1])).collect(),
+            _ => stack.iter().enumerate()
+                .map(|(i,v)| ((i + 1) as f64,v.to_f64()))
+                .filter(|(_,y)| y.is_finite())
+                .collect()
+        }
+    }
+    fn repeat_label(&self) -> String {
+        match &self.last_op {
+            None => "Rpt".to_string(),
+            Some((c,None,None)) => format!("Rpt {}",Self::op_label(c.0)),
+            Some((c,Some(top),None)) => format!("Rpt {top} {}",Self::op_label(c.0)),
+            Some((c,_,Some(operand))) => format!("{}{}",Self::op_label(c.0),operand)
+        }
+    }
 }
 
 impl Component for Calculator {
     type Message = CalculatorMsg;
     type Properties = CalculatorProp;
     fn create(ctx: &Context<Self>) -> Self {
-        ctx.props().scope_snd.send(ctx.link().clone());
+        let (config, _config_handle) = ctx.link()
+            .context::<SharedConfig>(ctx.link().callback(CalculatorMsg::ConfigChanged))
+            .expect("Calculator must be rendered inside a SharedConfig provider");
+        let _key_listener = web_sys::window().and_then(|w| w.document()).map(|d| {
+            let link = ctx.link().clone();
+            EventListener::new(&d,"keydown",move |e| {
+                // Typing into the matrix editor is not meant for the keypad.
+                if e.target().map_or(false,|t| t.has_type::<web_sys::HtmlInputElement>()) {
+                    return;
+                }
+                if let Some(e) = e.dyn_ref::<web_sys::KeyboardEvent>() {
+                    if !e.ctrl_key() && !e.meta_key() && !e.alt_key() {
+                        link.send_message(CalculatorMsg::KeyDown(e.key()));
+                    }
+                }
+            })
+        });
+        let _subscriptions = vec![
+            ctx.props().bus.subscribe(ctx.link().callback(|PushPoint(x,y)| CalculatorMsg::PushPoint(x,y)))
+        ];
+        let mut calc_unit = crate::calc_unit::CalcUnit::default();
+        calc_unit.set_model(config.stack_model);
+        calc_unit.set_backend(config.backend);
         Calculator {
-            visible: ctx.props().visible,
             display: "0".to_string(),
-            calc_unit: Default::default(),
-            stack_affected: false
+            entering: false,
+            lift_disabled: false,
+            last_entry: None,
+            last_op: None,
+            calc_unit,
+            stack_affected: false,
+            unit_picker: false,
+            unit_prefix: 0,
+            matrix_editor: None,
+            inspecting: None,
+            stat_data: false,
+            amortization: None,
+            config,
+            _config_handle,
+            _subscriptions,
+            _key_listener
         }
     }
     fn view(&self, ctx: &Context<Self>) -> Html {
-        if self.visible {
-            let parent: Scope<super::App> = ctx.link().get_parent().unwrap().clone().downcast();
-            let keypad = (0..=2u8)
-                .map(|d| (d*3+1,d*3+2,d*3+3))
-                .zip(vec![
-                    ('a','b','c'),
-                    ('d','e','f'),
-                    ('g','h','i')
-                ])
-                .flat_map(|((a,b,c),(a_ar,b_ar,c_ar))|
-                    vec![
-                        html!{<button class={{css!{grid-area: ${a_ar};}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::DigitInput(a)) }> { a } </button>},
-                        html!{<button class={{css!{grid-area: ${b_ar};}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::DigitInput(b)) }> { b } </button>},
-                        html!{<button class={{css!{grid-area: ${c_ar};}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::DigitInput(c)) }> { c } </button>}
-                    ]
-                ).chain(vec![
-                        html!{<button class={{css!{grid-area: j;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::DigitInput(0)) }> { 0   } </button>},
-                        html!{<button class={{css!{grid-area: k;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::Dot)           }> { "." } </button>},
-                        html!{<button class={{css!{grid-area: l;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::Backspace)     }> {"<-" } </button>},
-                        html!{<button class={{css!{grid-area: m;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                                        CommOrOpWrapper(CommOrOp::Op(
-                                            Op::Arith(
-                                                Arith::Add
-                                            )
-                                        ))
-                                    )
-                                )}
-                            > {"+"} </button>},
-                        html!{<button class={{css!{grid-area: n;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                                        CommOrOpWrapper(CommOrOp::Op(
-                                            Op::Arith(
-                                                Arith::Sub
-                                            )
-                                        ))
-                                    )
-                                )}> {"-"} </button>},
-                        html!{<button class={{css!{grid-area: o;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                                        CommOrOpWrapper(CommOrOp::Op(
-                                            Op::Arith(
-                                                Arith::Mul
-                                            )
-                                        ))
-                                    )
-                                )}> {"*"} </button>},
-                        html!{<button class={{css!{grid-area: p;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                                        CommOrOpWrapper(CommOrOp::Op(
-                                            Op::Arith(
-                                                Arith::Div
-                                            )
-                                        ))
-                                    )
-                                )}> {"/"} </button>},
-                        html!{<button class={{css!{grid-area: q;}}}onclick={ ctx.link().callback(move |_| CalculatorMsg::InsNum ) }> {"Ins"} </button>},
-                ]).collect::<Vec<_>>();
-            let stack_btns = html!{
-                < >
-                    <button onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Command(
-                            Command::Drop
-                        ))
-                    )) }> { "Drop" } </button>
-                    <button onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Command(
-                            Command::Dup
-                        ))
-                    )) }> { "Dup" } </button>
-                    <button onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Command(
-                            Command::Swap
-                        ))
-                    )) }> { "Swap" } </button>
-                    <button onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Command(
-                            Command::Rev
-                        ))
-                    )) }> { "Rev" } </button>
-                </>
-            };
-            let const_btns = html!{
-                < >
-                    <button onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Constant(
-                                Constant::Pi
-                            )
-                        ))
-                    )) }> {"Pi"} </button>
-                    <button onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Constant(
-                                Constant::E
-                            )
-                        ))
-                    )) }> {"E"} </button>
-                    <button onclick={ ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Constant(
-                                Constant::Inf
-                            )
-                        ))
-                    )) }> {"Inf"} </button>
-                    <div></div>
-                </>
-            };
-            let pow2_btns = html!{
-                < >
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Exp2(
-                                Exp2::Pow
-                            )
-                        ))
-                    ))}> { "Pow" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Exp2(
-                                Exp2::LogN
-                            )
-                        ))
-                    ))}> { "LogN" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Exp2(
-                                Exp2::RootN
-                            )
-                        ))
-                    ))}> { "RootN" } </button>
-                    <div></div>
-                </>
-            };
-            let pow_btns = html!{
-                < >
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Exp(
-                                Exp::Log10
-                            )
-                        ))
-                    ))}> { "Log10" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Exp(
-                                Exp::Log2
-                            )
-                        ))
-                    ))}> { "Log2" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Exp(
-                                Exp::LogE
-                            )
-                        ))
-                    ))}> { "LogE" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Exp(
-                                Exp::Root2
-                            )
-                        ))
-                    ))}> { "Root2" } </button>
-                </>
-            };
-            let trig_btns = html!{
-                < >
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Trig(
-                                Trig::Sin
-                            )
-                        ))
-                    ))}> { "Sin" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Trig(
-                                Trig::Cos
-                            )
-                        ))
-                    ))}> { "Cos" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Trig(
-                                Trig::Tan
-                            )
-                        ))
-                    ))}> { "Tan" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Trig(
-                                Trig::ASin
-                            )
-                        ))
-                    ))}> { "ASin" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Trig(
-                                Trig::ACos
-                            )
-                        ))
-                    ))}> { "ACos" } </button>
-                    <button onclick={ctx.link().callback(move |_| CalculatorMsg::CommOrOp(
-                        CommOrOpWrapper(CommOrOp::Op(
-                            Op::Trig(
-                                Trig::ATan
-                            )
-                        ))
-                    ))}> { "ATan" } </button>
-                </>
-            };
+        if ctx.props().visible {
+            let pages = self.config.keypad_layout.pages.iter().map(|page| {
+                let columns = page.columns();
+                let rows = page.rows.len();
+                let cells = page.rows.iter()
+                    .flat_map(|row| row.iter().copied().chain(std::iter::repeat(None).take(columns - row.len())))
+                    .map(|cell| match cell {
+                        Some(action) => {
+                            let label = match action {
+                                KeyAction::Repeat => self.repeat_label(),
+                                KeyAction::Record => self.record_label(),
+                                _ => action.label()
+                            };
+                            html!{<button title={ action.name() } onclick={ ctx.link().callback(move |_| action.msg()) }> { label } </button>}
+                        },
+                        None => html!{<div></div>}
+                    }).collect::<Html>();
+                html!{
+                    <div class={{css!{
+                        scroll-snap-align: start;
+                        display: grid;
+                        width: 100vw;
+                        height: 100%;
+                        grid-template-columns: repeat(${columns}, 1fr);
+                        grid-template-rows: repeat(${rows}, 1fr);
+                    }}}>
+                        { cells }
+                    </div>
+                }
+            }).collect::<Html>();
             let stack_slice = self.calc_unit.get_stack();
             let stack = if stack_slice.len() == 0 {
                 html!{
@@ -399,33 +469,37 @@ impl Component for Calculator {
                     .collect::<Vec<_>>()
                     .into_iter()
                     .rev()
-                    .map(|(i,num)|
-                        format!("{i:3}.- {}",rpncalc::format_num(*num))
-                    ).map(|s|
+                    .map(|(i,num)| (i,num.format(&self.number_format())))
+                    .map(|(i,num)| (i,match self.calc_unit.model() {
+                        StackModel::Fixed(_) => format!("{:>3}: {num}",Self::register_name(i)),
+                        StackModel::Unbounded => format!("{i:3}.- {num}")
+                    })).map(|(i,s)|
                         html!{
-                            <p class={{css!{text-indent: 1vh; margin: 0;}}}> { s } </p>
+                            <p class={{css!{text-indent: 1vh; margin: 0; white-space: pre; overflow-x: auto;}}} onclick={ ctx.link().callback(move |_| CalculatorMsg::Inspect(Some(i))) }> { s } </p>
                         }
                     ).collect::<Html>()
             };
+            let keypad = if let Some(initial) = &self.matrix_editor {
+                html!{ <MatrixEditor initial={ initial.clone() } on_done={ ctx.link().callback(CalculatorMsg::MatrixDone) }/> }
+            } else if self.unit_picker {
+                self.unit_picker(ctx)
+            } else {
+                html!{
+                    <div    class={{css!{display: grid; grid-auto-flow: column; grid-template-rows: 100%; scroll-snap-type: x mandatory; grid-area: d; overflow-x: scroll; overflow-y: hidden;}}}>
+                        { pages }
+                    </div>
+                }
+            };
             let stack_align = if stack_slice.len() == 0 {
                 "right"
             } else {
                 "left"
             };
-
-            html!{
-                <div class={{css!{
-                    width: 100%;
-                    height: 100%;
-                    display: grid;
-                    grid-gap: 0;
-                    grid:   "a b b b b" 1fr
-                            ". b b b b" 2fr
-                            "c c c c c" 1fr
-                            "d d d d d" 2fr
-                            "d d d d d" 2fr;
-                }}}>
-                    <button class={{css!{grid-area: a;}}} onclick={ move |_| parent.send_message(super::AppMsg::ShowLoggingTray)}> { "L" } </button>
+            let stack_view = match self.inspecting {
+                _ if self.stat_data => self.stat_data_view(ctx),
+                _ if self.amortization.is_some() => self.amortization_view(ctx,self.amortization.as_ref().unwrap()),
+                Some(level) if level < stack_slice.len() => self.inspector(ctx,level),
+                _ => html!{
                     <div id="stackscroller" class={{css!{grid-area: b; scroll-snap-type: y proximity;
                         overflow: hidden; overflow-y: scroll;}}}
                     >
@@ -442,47 +516,26 @@ impl Component for Calculator {
                             scroll-snap-align: end;
                         }}}></div>
                     </div>
+                }
+            };
+
+            html!{
+                <div class={{css!{
+                    width: 100%;
+                    height: 100%;
+                    display: grid;
+                    grid-gap: 0;
+                    grid:   "a b b b b" 1fr
+                            "s b b b b" 2fr
+                            "c c c c c" 1fr
+                            "d d d d d" 2fr
+                            "d d d d d" 2fr;
+                }}}>
+                    <button class={{css!{grid-area: a;}}} onclick={ |_| Route::Log.navigate() }> { "L" } </button>
+                    <button class={{css!{grid-area: s;}}} onclick={ |_| Route::Settings.navigate() }> { "S" } </button>
+                    { stack_view }
                     <div    class={{css!{grid-area: c; text-align: right; overflow-x: hidden; text-overflow: ellipsis;}}}> { self.display.as_str() } </div>
-                    <div    class={{css!{display: grid; grid: ". . ." 1fr; scroll-snap-type: x mandatory; grid-area: d; overflow-x: scroll; overflow-y: hidden;}}}>
-                        <div class={{css!{
-                            scroll-snap-align: start;
-                            display: grid;
-                            width: 100vw;
-                            height: 100%;
-                            grid:   "a b c m" 1fr
-                                    "d e f n" 1fr
-                                    "g h i o" 1fr
-                                    "k j l p" 1fr
-                                    ". . . q" 1fr;
-                        }}}>
-                            { for keypad }
-                        </div>
-                        <div class={{css!{
-                            scroll-snap-align: start;
-                            display: grid;
-                            width: 100vw;
-                            height: 100%;
-                            grid:   ". . . ." 1fr
-                                    ". . . ." 1fr
-                                    ". . . ." 1fr
-                                    ". . . ." 1fr;
-                        }}}>
-                            { stack_btns }
-                            { const_btns }
-                            {  pow_btns  }
-                            {  pow2_btns }
-                        </div>
-                        <div class={{css!{
-                            scroll-snap-align: start;
-                            display: grid;
-                            width: 100vw;
-                            height: 100%;
-                            grid:   ". . ." 1fr
-                                    ". . ." 1fr;
-                        }}}>
-                            { trig_btns }
-                        </div>
-                    </div>
+                    { keypad }
                 </div>
             }
         } else { debug!("Rendering Calculator as Invisible"); html!{} }
@@ -490,42 +543,269 @@ impl Component for Calculator {
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         self.stack_affected = false;
         match msg {
-            CalculatorMsg::Show => self.visible = true,
-            CalculatorMsg::Hide => self.visible = false,
-            CalculatorMsg::DigitInput(d) => if &self.display == "0" {
-                self.display = d.to_string();
-            } else {
-                self.display += &d.to_string();
+            CalculatorMsg::DigitInput(d) => {
+                let digit = match std::char::from_digit(d as u32,self.config.number_format.base.radix()) {
+                    Some(c) => c.to_ascii_uppercase(),
+                    None => return false
+                };
+                if &self.display == "0" {
+                    self.display = digit.to_string();
+                } else {
+                    self.display.push(digit);
+                }
+                self.entering = true;
+            },
+            CalculatorMsg::Backspace => {
+                if self.display.pop() == Some('i') {
+                    self.display.pop();
+                }
+                if self.display.len() == 0 || &self.display == "0" { self.display = "0".to_string(); self.entering = false; }
+            },
+            CalculatorMsg::Dot => {
+                if self.config.number_format.base != Base::Dec {
+                    return false;
+                }
+                let part = self.display.rsplit(|c| c == 'i' || c == '∠').next().unwrap_or("");
+                if !part.contains('.') { self.display.push('.') }
+                self.entering = true;
+            },
+            CalculatorMsg::Imag => {
+                if self.display.ends_with("+i") {
+                    self.display.replace_range(self.display.len() - 2..,"-i");
+                } else if self.display.ends_with("-i") {
+                    self.display.replace_range(self.display.len() - 2..,"+i");
+                } else if !self.entry_is_complex() {
+                    self.display += "+i";
+                }
+                self.entering = true;
+            },
+            CalculatorMsg::SetBase(base) => {
+                let from = self.config.number_format.base;
+                if self.entering && base != from {
+                    // Only whole numbers have digits in every base, so any
+                    // other entry keeps the base as it is.
+                    let n = match i128::from_str_radix(&self.display,from.radix()) {
+                        Ok(n) => n,
+                        Err(_) => {
+                            ctx.props().bus.publish(Log(format!("{}\n",CalcError::NotAnInteger(self.display.clone()))));
+                            return false;
+                        }
+                    };
+                    self.display = match base {
+                        Base::Bin => format!("{n:b}"),
+                        Base::Oct => format!("{n:o}"),
+                        Base::Dec => n.to_string(),
+                        Base::Hex => format!("{n:X}")
+                    };
+                }
+                let mut config = self.config.clone();
+                config.number_format.base = base;
+                ctx.props().bus.publish(SetConfig(config));
+            },
+            CalculatorMsg::UnitPicker(open) => self.unit_picker = open,
+            CalculatorMsg::EditMatrix(initial) => {
+                self.inspecting = None;
+                self.matrix_editor = Some(initial);
+            },
+            CalculatorMsg::MatrixDone(value) => {
+                self.matrix_editor = None;
+                if let Some(value) = value {
+                    self.begin_step();
+                    if !Self::check(ctx,self.consume_entry()) {
+                        return true;
+                    }
+                    self.calc_unit.push(value);
+                }
+            },
+            CalculatorMsg::Inspect(level) => self.inspecting = level,
+            CalculatorMsg::StatData(open) => self.stat_data = open,
+            CalculatorMsg::EditStat(i,is_y,text) => {
+                let mut point = match self.calc_unit.stat_points().get(i) {
+                    Some(&point) => point,
+                    None => return Self::check(ctx,Err(CalcError::NoSuchPoint))
+                };
+                match (is_y,text.trim().parse::<f64>()) {
+                    (false,Ok(x)) => point.x = x,
+                    (true,Ok(y)) => point.y = Some(y),
+                    (true,Err(_)) if text.trim().is_empty() => point.y = None,
+                    (_,Err(_)) => {
+                        // Redrawn so the field shows the point again.
+                        Self::check(ctx,Err(CalcError::InvalidNumber(text))).ignore();
+                        return true;
+                    }
+                }
+                // Its own step, so undoing it leaves earlier steps alone.
+                self.calc_unit.checkpoint();
+                Self::check(ctx,self.calc_unit.edit_stat_point(i,Some(point))).ignore();
+            },
+            CalculatorMsg::DeleteStat(i) => {
+                self.calc_unit.checkpoint();
+                Self::check(ctx,self.calc_unit.edit_stat_point(i,None)).ignore();
+            },
+            CalculatorMsg::Amortization(open) => self.amortization = open.then(BTreeSet::new),
+            CalculatorMsg::SelectPayment(period) => if let Some(selected) = &mut self.amortization {
+                if !selected.remove(&period) {
+                    selected.insert(period);
+                }
+            },
+            CalculatorMsg::PushPayments => if let Some(selected) = self.amortization.take() {
+                let (interest,principal) = self.calc_unit.tvm().schedule().iter()
+                    .filter(|row| selected.contains(&row.period))
+                    .fold((0.0,0.0),|(i,p),row| (i + row.interest,p + row.principal));
+                self.begin_step();
+                if !Self::check(ctx,self.consume_entry()) {
+                    return true;
+                }
+                self.last_entry = None;
+                self.calc_unit.push(Value::Float(interest));
+                self.calc_unit.push(Value::Float(principal));
+            },
+            CalculatorMsg::UnitPrefix(p) => self.unit_prefix = p,
+            CalculatorMsg::Unit(unit) => {
+                self.begin_step();
+                self.unit_picker = false;
+                if !Self::check(ctx,self.consume_entry()) {
+                    return true;
+                }
+                self.last_entry = None;
+                self.execute(ctx,CommOrOp::Native(Native::Units(UnitOp::To(unit))),None);
+            },
+            CalculatorMsg::Polar => {
+                if !self.entry_is_complex() {
+                    self.display.push('∠');
+                }
+                self.entering = true;
             },
-            CalculatorMsg::Backspace => { self.display.pop(); if self.display.len() == 0 { self.display.push('0'); } },
-            CalculatorMsg::Dot => if !self.display.contains('.') { self.display.push('.') },
             CalculatorMsg::InsNum => {
-                self.stack_affected = true;
-                let ins_num = rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::InsNum(rpncalc::ops::InsNum::from_str(&self.display).unwrap()));
-                self.display = "0".to_string();
-                self.calc_unit.run_command(ins_num).ignore();
+                self.begin_step();
+                match self.config.entry_mode {
+                    EntryMode::AutoEnter | EntryMode::Manual => Self::check(ctx,self.push_entry()).ignore(),
+                    EntryMode::StackLift => if Self::check(ctx,self.lift_entry()) {
+                        self.calc_unit.apply(CommOrOp::Command(Command::Dup)).ignore();
+                        self.lift_disabled = true;
+                    }
+                }
             }
             CalculatorMsg::CommOrOp(c) => {
+                self.begin_step();
+                let top = match self.entry_for(c.0) {
+                    Ok(top) => top,
+                    Err(e) => {
+                        ctx.props().bus.publish(Log(format!("{e}\n")));
+                        return true;
+                    }
+                };
+                let operand = match c.0.arity() {
+                    Arity::Fixed(2) => self.last_entry.take(),
+                    _ => None
+                };
+                self.last_entry = None;
+                self.last_op = Some((c,top,operand));
+                self.execute(ctx,c.0,top);
+            },
+            CalculatorMsg::Repeat => if let Some((c,top,operand)) = self.last_op.clone() {
+                self.begin_step();
+                if !Self::check(ctx,self.consume_entry()) {
+                    return true;
+                }
+                self.last_entry = None;
+                if let Some(operand) = operand {
+                    match self.parse_number(&operand) {
+                        Ok(v) => self.calc_unit.push(v),
+                        Err(e) => {
+                            ctx.props().bus.publish(Log(format!("{e}\n")));
+                            return true;
+                        }
+                    }
+                }
+                self.execute(ctx,c.0,top);
+            },
+            CalculatorMsg::Undo => {
                 self.stack_affected = true;
-                let (stdout,res) = self.calc_unit.run_command(c.command_or_op());
-                let mut out = String::new();
-                out += &std::string::String::from_utf8_lossy(&stdout);
-                out.push('\n');
-                match res {
-                    Ok(Some(s)) => out += &s,
-                    Err(e) => out += &format!("{e:?}"),
-                    _ => ()
+                self.lift_disabled = false;
+                if let Err(e) = self.calc_unit.undo() {
+                    ctx.props().bus.publish(Log(format!("{e}\n")));
+                }
+            },
+            CalculatorMsg::Record => {
+                // X is x for the formula, so an entry still being typed is
+                // pushed before recording starts and recorded before it stops.
+                self.begin_step();
+                if !Self::check(ctx,self.consume_entry()) {
+                    return true;
+                }
+                self.last_entry = None;
+                if self.calc_unit.recording() {
+                    let formula = self.calc_unit.stop_recording().clone();
+                    ctx.props().bus.publish(Log(format!("{}\n",self.formula_text(&formula))));
+                } else {
+                    self.calc_unit.start_recording();
                 }
-                out.push('\n');
-                let parent_scope: Scope<super::App> = ctx.link().get_parent().unwrap().clone().downcast();
-                parent_scope.send_message(super::AppMsg::LogMsg(out));
+            },
+            CalculatorMsg::PlotFormula => {
+                let formula = self.calc_unit.formula();
+                if formula.steps.is_empty() {
+                    ctx.props().bus.publish(Log(format!("{}\n",CalcError::NoFormula)));
+                } else {
+                    ctx.props().bus.publish(PlotSeries::Formula(formula.clone(),self.formula_text(formula)));
+                    Route::Plot.navigate();
+                }
+                return false;
+            },
+            CalculatorMsg::PlotStack => {
+                let points = self.stack_points();
+                if points.is_empty() {
+                    ctx.props().bus.publish(Log("Nothing on the stack to plot\n".to_string()));
+                } else {
+                    ctx.props().bus.publish(PlotSeries::Points(points));
+                    Route::Plot.navigate();
+                }
+                return false;
+            },
+            CalculatorMsg::PushPoint(x,y) => {
+                self.begin_step();
+                if !Self::check(ctx,self.consume_entry()) {
+                    return true;
+                }
+                self.last_entry = None;
+                self.calc_unit.push(Value::Float(y));
+                self.calc_unit.push(Value::Float(x));
+            },
+            CalculatorMsg::KeyDown(key) => {
+                let hex_digit = match key.as_bytes() {
+                    &[c] if self.config.number_format.base == Base::Hex && c.is_ascii_hexdigit() => Some(KeyAction::Digit((c as char).to_digit(16).unwrap() as u8)),
+                    _ => None
+                };
+                return match hex_digit.or_else(|| KeyAction::from_key(&key)) {
+                    Some(action) if ctx.props().visible => self.update(ctx,action.msg()),
+                    _ => false
+                };
+            },
+            CalculatorMsg::ConfigChanged(config) => {
+                // Every field shows somewhere, down to the theme's colors.
+                let changed = self.config != config;
+                if self.config.stack_model != config.stack_model {
+                    self.calc_unit.set_model(config.stack_model);
+                }
+                if self.config.backend != config.backend {
+                    self.calc_unit.set_backend(config.backend);
+                }
+                if self.config.entry_mode != config.entry_mode {
+                    self.lift_disabled = false;
+                }
+                self.config = config;
+                return changed;
             }
         }
         true
     }
     fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
         if self.stack_affected {
-            let elm = web_sys::window().unwrap().document().unwrap().get_element_by_id("stackscroller").unwrap();
+            // Absent while the inspector covers the stack.
+            let elm = match web_sys::window().unwrap().document().unwrap().get_element_by_id("stackscroller") {
+                Some(elm) => elm,
+                None => return
+            };
             elm.scroll_to_with_scroll_to_options(&{
                 let mut opt = web_sys::ScrollToOptions::new();
                 opt.left(0.0);
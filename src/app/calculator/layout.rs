@@ -0,0 +1,511 @@
+use super::{CalculatorMsg,CommOrOpWrapper};
+use super::registry;
+use crate::calc_unit::Base;
+use std::fmt;
+
+/// What a single key does when pressed.
+#[derive(Clone,Copy,PartialEq)]
+pub enum KeyAction {
+    /// 0 to 15; digits past the entry base are ignored.
+    Digit(u8),
+    Dot,
+    /// Starts the imaginary part of a rectangular entry, or flips its sign.
+    Imag,
+    /// Starts the angle of a polar entry.
+    Polar,
+    Backspace,
+    Enter,
+    /// Re-runs the last op, see `Calculator::repeat_label`.
+    Repeat,
+    /// Puts the stack back as it was before the last step.
+    Undo,
+    /// Switches the base integers are entered and shown in.
+    Base(Base),
+    /// Opens the unit picker.
+    Units,
+    /// Opens the vector and matrix editor.
+    Matrix,
+    /// Shows the accumulated statistics points for editing.
+    StatData,
+    /// Shows the TVM registers and amortization schedule.
+    Amort,
+    /// Starts or stops recording the formula, see `Calculator::record_label`.
+    Record,
+    /// Plots the recorded formula.
+    PlotFormula,
+    /// Plots the stack as points.
+    PlotStack,
+    Op(CommOrOpWrapper)
+}
+
+impl Base {
+    fn token(self) -> &'static str {
+        match self {
+            Base::Bin => "bin",
+            Base::Oct => "oct",
+            Base::Dec => "dec",
+            Base::Hex => "hex"
+        }
+    }
+}
+
+fn digit_char(d: u8) -> char {
+    std::char::from_digit(d as u32,16).unwrap().to_ascii_uppercase()
+}
+
+impl KeyAction {
+    pub fn from_token(token: &str) -> Option<Self> {
+        Some(match token {
+            "." => KeyAction::Dot,
+            "imag" => KeyAction::Imag,
+            "polar" => KeyAction::Polar,
+            "bksp" => KeyAction::Backspace,
+            "enter" => KeyAction::Enter,
+            "repeat" => KeyAction::Repeat,
+            "undo" => KeyAction::Undo,
+            "units" => KeyAction::Units,
+            "matrix" => KeyAction::Matrix,
+            "data" => KeyAction::StatData,
+            "amort" => KeyAction::Amort,
+            "rec" => KeyAction::Record,
+            "plotf" => KeyAction::PlotFormula,
+            "plotd" => KeyAction::PlotStack,
+            "bin" => KeyAction::Base(Base::Bin),
+            "oct" => KeyAction::Base(Base::Oct),
+            "dec" => KeyAction::Base(Base::Dec),
+            "hex" => KeyAction::Base(Base::Hex),
+            d if d.len() == 1 && d.as_bytes()[0].is_ascii_digit() => KeyAction::Digit(d.as_bytes()[0] - b'0'),
+            d if d.len() == 1 && (b'A'..=b'F').contains(&d.as_bytes()[0]) => KeyAction::Digit(d.as_bytes()[0] - b'A' + 10),
+            _ => KeyAction::Op(registry::by_id(token)?.op)
+        })
+    }
+    pub fn token(&self) -> String {
+        match self {
+            KeyAction::Digit(d) => digit_char(*d).to_string(),
+            KeyAction::Dot => ".".to_string(),
+            KeyAction::Imag => "imag".to_string(),
+            KeyAction::Polar => "polar".to_string(),
+            KeyAction::Backspace => "bksp".to_string(),
+            KeyAction::Enter => "enter".to_string(),
+            KeyAction::Repeat => "repeat".to_string(),
+            KeyAction::Undo => "undo".to_string(),
+            KeyAction::Base(b) => b.token().to_string(),
+            KeyAction::Units => "units".to_string(),
+            KeyAction::Matrix => "matrix".to_string(),
+            KeyAction::StatData => "data".to_string(),
+            KeyAction::Amort => "amort".to_string(),
+            KeyAction::Record => "rec".to_string(),
+            KeyAction::PlotFormula => "plotf".to_string(),
+            KeyAction::PlotStack => "plotd".to_string(),
+            KeyAction::Op(op) => registry::by_op(*op).map_or("?",|def| def.id).to_string()
+        }
+    }
+    pub fn label(&self) -> String {
+        match self {
+            KeyAction::Digit(d) => digit_char(*d).to_string(),
+            KeyAction::Dot => ".".to_string(),
+            KeyAction::Imag => "±i".to_string(),
+            KeyAction::Polar => "∠".to_string(),
+            KeyAction::Backspace => "<-".to_string(),
+            KeyAction::Enter => "Ins".to_string(),
+            KeyAction::Repeat => "Rpt".to_string(),
+            KeyAction::Undo => "Undo".to_string(),
+            KeyAction::Base(b) => b.token().to_uppercase(),
+            KeyAction::Units => "Unit".to_string(),
+            KeyAction::Matrix => "[ ]".to_string(),
+            KeyAction::StatData => "Data".to_string(),
+            KeyAction::Amort => "AMORT".to_string(),
+            KeyAction::Record => "Rec".to_string(),
+            KeyAction::PlotFormula => "Plot f".to_string(),
+            KeyAction::PlotStack => "Plot ⋰".to_string(),
+            KeyAction::Op(op) => registry::by_op(*op).map_or("?",|def| def.label).to_string()
+        }
+    }
+    /// Long description, for tooltips.
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyAction::Digit(_) => "Digit",
+            KeyAction::Dot => "Decimal point",
+            KeyAction::Imag => "Imaginary part",
+            KeyAction::Polar => "Polar angle",
+            KeyAction::Backspace => "Delete last digit",
+            KeyAction::Enter => "Push the entry onto the stack",
+            KeyAction::Repeat => "Repeat the last operation",
+            KeyAction::Undo => "Undo the last step",
+            KeyAction::Base(Base::Bin) => "Binary",
+            KeyAction::Base(Base::Oct) => "Octal",
+            KeyAction::Base(Base::Dec) => "Decimal",
+            KeyAction::Base(Base::Hex) => "Hexadecimal",
+            KeyAction::Units => "Attach or convert to a unit",
+            KeyAction::Matrix => "Enter a vector or matrix",
+            KeyAction::StatData => "Show and edit the statistics data",
+            KeyAction::Amort => "Show the TVM registers and amortization schedule",
+            KeyAction::Record => "Record a formula of X, or stop recording",
+            KeyAction::PlotFormula => "Plot the recorded formula",
+            KeyAction::PlotStack => "Plot the stack, or the rows of a two-column matrix in X, as points",
+            KeyAction::Op(op) => registry::by_op(*op).map_or("",|def| def.name)
+        }
+    }
+    /// Maps a `KeyboardEvent.key` to the action it triggers.
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "Backspace" => Some(KeyAction::Backspace),
+            "Enter" => Some(KeyAction::Enter),
+            "=" => Some(KeyAction::Repeat),
+            "z" => Some(KeyAction::Undo),
+            "." | "," => Some(KeyAction::Dot),
+            "j" => Some(KeyAction::Imag),
+            "@" => Some(KeyAction::Polar),
+            "u" => Some(KeyAction::Units),
+            "[" => Some(KeyAction::Matrix),
+            d if d.len() == 1 && d.as_bytes()[0].is_ascii_digit() => Some(KeyAction::Digit(d.as_bytes()[0] - b'0')),
+            _ => registry::by_shortcut(key).map(|def| KeyAction::Op(def.op))
+        }
+    }
+    pub fn msg(&self) -> CalculatorMsg {
+        match *self {
+            KeyAction::Digit(d) => CalculatorMsg::DigitInput(d),
+            KeyAction::Dot => CalculatorMsg::Dot,
+            KeyAction::Imag => CalculatorMsg::Imag,
+            KeyAction::Polar => CalculatorMsg::Polar,
+            KeyAction::Backspace => CalculatorMsg::Backspace,
+            KeyAction::Enter => CalculatorMsg::InsNum,
+            KeyAction::Repeat => CalculatorMsg::Repeat,
+            KeyAction::Undo => CalculatorMsg::Undo,
+            KeyAction::Base(b) => CalculatorMsg::SetBase(b),
+            KeyAction::Units => CalculatorMsg::UnitPicker(true),
+            KeyAction::Matrix => CalculatorMsg::EditMatrix(None),
+            KeyAction::StatData => CalculatorMsg::StatData(true),
+            KeyAction::Amort => CalculatorMsg::Amortization(true),
+            KeyAction::Record => CalculatorMsg::Record,
+            KeyAction::PlotFormula => CalculatorMsg::PlotFormula,
+            KeyAction::PlotStack => CalculatorMsg::PlotStack,
+            KeyAction::Op(op) => CalculatorMsg::CommOrOp(op)
+        }
+    }
+}
+
+/// A keypad page: rows of cells, where `None` leaves a blank gap.
+#[derive(Clone,PartialEq)]
+pub struct Page {
+    pub rows: Vec<Vec<Option<KeyAction>>>
+}
+
+impl Page {
+    pub fn columns(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+/// The whole keypad, one horizontally snapping page after another.
+///
+/// Layouts are written as text so they can be edited by hand: one row per
+/// line, cells separated by whitespace, `_` for a blank cell and a line of
+/// `---` between pages.
+#[derive(Clone,PartialEq)]
+pub struct Layout {
+    pub pages: Vec<Page>
+}
+
+#[derive(Debug,PartialEq)]
+pub enum LayoutError {
+    UnknownToken { line: usize, token: String },
+    EmptyPage { line: usize },
+    NoPages
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::UnknownToken { line, token } => write!(f,"Line {line}: unknown key '{token}'"),
+            LayoutError::EmptyPage { line } => write!(f,"Line {line}: page has no rows"),
+            LayoutError::NoPages => write!(f,"Layout has no pages")
+        }
+    }
+}
+
+impl std::str::FromStr for Layout {
+    type Err = LayoutError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pages = vec![];
+        let mut rows = vec![];
+        for (i,line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.chars().all(|c| c == '-') {
+                if rows.is_empty() {
+                    return Err(LayoutError::EmptyPage { line: i + 1 });
+                }
+                pages.push(Page { rows: std::mem::take(&mut rows) });
+                continue;
+            }
+            let row = line.split_whitespace()
+                .map(|token| match token {
+                    "_" => Ok(None),
+                    _ => KeyAction::from_token(token)
+                        .map(Some)
+                        .ok_or_else(|| LayoutError::UnknownToken { line: i + 1, token: token.to_string() })
+                }).collect::<Result<Vec<_>,_>>()?;
+            rows.push(row);
+        }
+        if !rows.is_empty() {
+            pages.push(Page { rows });
+        }
+        if pages.is_empty() {
+            return Err(LayoutError::NoPages);
+        }
+        Ok(Layout { pages })
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i,page) in self.pages.iter().enumerate() {
+            if i != 0 {
+                writeln!(f,"---")?;
+            }
+            for row in &page.rows {
+                let tokens = row.iter()
+                    .map(|cell| cell.map(|a| a.token()).unwrap_or_else(|| "_".to_string()))
+                    .collect::<Vec<_>>();
+                writeln!(f,"{}",tokens.join(" "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Built-in layouts offered in the settings screen.
+#[derive(Clone,Copy,PartialEq)]
+pub enum Preset {
+    Standard,
+    Hp,
+    Compact,
+    Scientific,
+    Programmer
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [Preset::Standard,Preset::Hp,Preset::Compact,Preset::Scientific,Preset::Programmer];
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Standard   => "Standard",
+            Preset::Hp         => "HP-style",
+            Preset::Compact    => "Compact",
+            Preset::Scientific => "Scientific",
+            Preset::Programmer => "Programmer"
+        }
+    }
+    fn source(self) -> &'static str {
+        match self {
+            Preset::Standard => "
+                1 2 3 add
+                4 5 6 sub
+                7 8 9 mul
+                . 0 bksp div
+                undo _ repeat enter
+                ---
+                pct dpct pctt mu
+                7 8 9 mgn
+                4 5 6 pmgn
+                1 2 3 swap
+                0 . bksp enter
+                ---
+                tvm.n tvm.i tvm.pv tvm.pmt tvm.fv
+                tvm.n? tvm.i? tvm.pv? tvm.pmt? tvm.fv?
+                7 8 9 tvm.pyr tvm.beg
+                4 5 6 amort tvm.clr
+                1 2 3 sub enter
+                0 . bksp swap drop
+                ---
+                drop dup swap rev
+                pi e inf lastx
+                log10 log2 ln sqrt
+                pow logn rootn lastarg
+                ---
+                ssum sprod avg med
+                sort rsort min max
+                uniq rev undo plotd
+                ---
+                sin cos tan
+                asin acos atan
+                frac imag polar
+                conj re im
+                abs arg _
+                units conv uval
+                ---
+                sinh cosh tanh exp
+                asinh acosh atanh exp10
+                floor ceil rnd exp2
+                ip fp sign abs
+                mod idiv _ _
+                ---
+                fact gamma ncr npr
+                gcd lcm prime factor
+                modpow _ _ _
+                ---
+                matrix trn det
+                inv solve _
+                dot cross _
+                ---
+                s+ s+xy data sclr
+                s- s-xy n mean
+                sdev psdev slope icept
+                corr yhat xhat plotd
+                ---
+                rec root root2 plotf
+                integ deriv swap enter
+                7 8 9 div
+                4 5 6 mul
+                1 2 3 sub
+                0 . bksp add
+            ",
+            Preset::Hp => "
+                enter enter swap bksp
+                7 8 9 div
+                4 5 6 mul
+                1 2 3 sub
+                0 . drop add
+                ---
+                sqrt pow ln log10
+                sin cos tan pi
+                asin acos atan e
+                dup rev logn rootn
+                lastx lastarg repeat frac
+                imag polar conj abs
+                re im arg units
+                undo ssum sort uniq
+            ",
+            Preset::Compact => "
+                7 8 9 div drop
+                4 5 6 mul swap
+                1 2 3 sub dup
+                0 . bksp add enter
+                ---
+                pow sqrt ln log10 pi
+                sin cos tan e inf
+                asin acos atan logn rootn
+                lastx lastarg repeat frac undo
+                imag polar conj re im
+                abs arg units conv uval
+            ",
+            Preset::Scientific => "
+                sin cos tan pow sqrt
+                asin acos atan logn rootn
+                7 8 9 div ln
+                4 5 6 mul log10
+                1 2 3 sub log2
+                0 . bksp add enter
+                ---
+                drop dup swap rev
+                pi e inf undo
+                lastx lastarg repeat frac
+                imag polar conj abs
+                re im arg units
+                ---
+                sinh cosh tanh exp
+                asinh acosh atanh exp10
+                floor ceil rnd exp2
+                ip fp sign abs
+                mod idiv _ _
+                ---
+                fact gamma ncr npr
+                gcd lcm prime factor
+                modpow _ _ _
+                ---
+                s+ s+xy data sclr
+                s- s-xy n mean
+                sdev psdev slope icept
+                corr yhat xhat plotd
+                ---
+                rec root root2 integ deriv
+                pow sqrt ln swap plotf
+                7 8 9 div
+                4 5 6 mul
+                1 2 3 sub
+                0 . add enter
+            ",
+            Preset::Programmer => "
+                hex dec oct bin swap
+                A B C D E
+                F and or xor not
+                7 8 9 shl shr
+                4 5 6 rotl rotr
+                1 2 3 mul div
+                0 bksp enter add sub
+                ---
+                drop dup swap rev
+                pow abs lastx lastarg
+                mod idiv sign _
+                gcd lcm prime factor
+                undo _ repeat _
+            "
+        }
+    }
+    pub fn layout(self) -> Layout {
+        self.source().parse().expect("built-in layouts are well formed")
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Preset::Standard.layout()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Layout,LayoutError> {
+        text.parse()
+    }
+
+    #[test]
+    fn presets_round_trip() {
+        for preset in Preset::ALL {
+            let layout = preset.layout();
+            let text = layout.to_string();
+            let reparsed = parse(&text).unwrap_or_else(|e| panic!("{}: {e}",preset.name()));
+            assert!(reparsed == layout,"{} changed on the way through text",preset.name());
+            assert_eq!(reparsed.to_string(),text);
+        }
+    }
+
+    #[test]
+    fn unknown_tokens_are_rejected_with_their_line() {
+        assert_eq!(parse("1 2 frob").err(),Some(LayoutError::UnknownToken { line: 1, token: "frob".to_string() }));
+        // Blank lines still count.
+        assert_eq!(parse("1 2\n\n  3 G").err(),Some(LayoutError::UnknownToken { line: 3, token: "G".to_string() }));
+        assert_eq!(parse("a").err(),Some(LayoutError::UnknownToken { line: 1, token: "a".to_string() }));
+    }
+
+    #[test]
+    fn underscores_are_blank_cells() {
+        let layout = parse("_ 1\nF _ _").unwrap();
+        let rows = &layout.pages[0].rows;
+        assert!(rows[0] == vec![None,Some(KeyAction::Digit(1))]);
+        assert!(rows[1] == vec![Some(KeyAction::Digit(15)),None,None]);
+        assert_eq!(layout.pages[0].columns(),3);
+        assert_eq!(layout.to_string(),"_ 1\nF _ _\n");
+    }
+
+    #[test]
+    fn dashes_separate_pages() {
+        let layout = parse("1 2\n---\n3\n-----\n4").unwrap();
+        assert_eq!(layout.pages.len(),3);
+        assert_eq!(layout.to_string(),"1 2\n---\n3\n---\n4\n");
+        // A separator at the very end adds no page.
+        assert_eq!(parse("1\n---\n").unwrap().pages.len(),1);
+    }
+
+    #[test]
+    fn pages_need_rows() {
+        assert_eq!(parse("---\n1").err(),Some(LayoutError::EmptyPage { line: 1 }));
+        assert_eq!(parse("1\n---\n\n---\n2").err(),Some(LayoutError::EmptyPage { line: 4 }));
+        assert_eq!(parse("").err(),Some(LayoutError::NoPages));
+        assert_eq!(parse("  \n\n").err(),Some(LayoutError::NoPages));
+    }
+}
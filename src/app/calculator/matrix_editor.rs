@@ -0,0 +1,120 @@
+use yew::prelude::*;
+use stylist::css;
+use crate::calc_unit::Value;
+use crate::calc_unit::linalg::Matrix;
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn, error};
+
+pub enum MatrixEditorMsg {
+    Resize(usize,usize),
+    Edit(usize,String),
+    Push,
+    Cancel
+}
+
+#[derive(PartialEq,Properties)]
+pub struct MatrixEditorProps {
+    /// Vector or matrix to start from; an empty 2×2 grid otherwise.
+    pub initial: Option<Value>,
+    /// Called with the finished value, or `None` when editing is abandoned.
+    pub on_done: Callback<Option<Value>>
+}
+
+/// Grid of cells for typing in a vector or matrix. A single row becomes a
+/// vector.
+pub struct MatrixEditor {
+    rows: usize,
+    cols: usize,
+    cells: Vec<String>,
+    error: Option<String>
+}
+
+impl MatrixEditor {
+    const MAX_SIZE: usize = 8;
+    fn parse(&self) -> Result<Value,String> {
+        let data = self.cells.iter().enumerate().map(|(i,cell)| match cell.trim() {
+            "" => Ok(0.0),
+            s => s.parse::<f64>().map_err(|_| format!("Row {}, column {}: '{s}' is not a number",i / self.cols + 1,i % self.cols + 1))
+        }).collect::<Result<Vec<_>,_>>()?;
+        Ok(match self.rows {
+            1 => Value::Vector(data),
+            rows => Value::Matrix(Matrix::new(rows,self.cols,data))
+        })
+    }
+}
+
+impl Component for MatrixEditor {
+    type Message = MatrixEditorMsg;
+    type Properties = MatrixEditorProps;
+    fn create(ctx: &Context<Self>) -> Self {
+        let show = |x: &f64| rpncalc::format_num(*x).to_string();
+        let (rows,cols,cells) = match &ctx.props().initial {
+            Some(Value::Vector(v)) => (1,v.len(),v.iter().map(show).collect()),
+            Some(Value::Matrix(m)) => (m.rows,m.cols,m.data.iter().map(show).collect()),
+            _ => (2,2,vec![String::new(); 4])
+        };
+        MatrixEditor { rows, cols, cells, error: None }
+    }
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let (rows,cols) = (self.rows,self.cols);
+        let resize = |label: &'static str, r: usize, c: usize| {
+            let disabled = r == 0 || c == 0 || r > Self::MAX_SIZE || c > Self::MAX_SIZE;
+            html!{<button disabled={ disabled } onclick={ ctx.link().callback(move |_| MatrixEditorMsg::Resize(r,c)) }> { label } </button>}
+        };
+        let cells = self.cells.iter().enumerate().map(|(i,cell)| html!{
+            <input class={{css!{width: 100%; box-sizing: border-box;}}} value={ cell.clone() } inputmode="decimal"
+                oninput={ ctx.link().callback(move |e: InputEvent| MatrixEditorMsg::Edit(i,e.target_unchecked_into::<web_sys::HtmlInputElement>().value())) }/>
+        }).collect::<Html>();
+        let error = match &self.error {
+            Some(e) => html!{ <p> { e.as_str() } </p> },
+            None => html!{}
+        };
+        html!{
+            <div class={{css!{grid-area: d; overflow-y: scroll;}}}>
+                <div>
+                    { format!("{rows}×{cols} ") }
+                    { resize("Row+",rows + 1,cols) }
+                    { resize("Row-",rows - 1,cols) }
+                    { resize("Col+",rows,cols + 1) }
+                    { resize("Col-",rows,cols - 1) }
+                </div>
+                <div class={{css!{display: grid; width: 100%; grid-template-columns: repeat(${cols}, 1fr);}}}> { cells } </div>
+                { error }
+                <button onclick={ ctx.link().callback(|_| MatrixEditorMsg::Push) }> { "Push" } </button>
+                <button onclick={ ctx.link().callback(|_| MatrixEditorMsg::Cancel) }> { "Cancel" } </button>
+            </div>
+        }
+    }
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            MatrixEditorMsg::Resize(rows,cols) => {
+                self.cells = (0..rows).flat_map(|r| (0..cols).map(move |c| (r,c)))
+                    .map(|(r,c)| match r < self.rows && c < self.cols {
+                        true => std::mem::take(&mut self.cells[r * self.cols + c]),
+                        false => String::new()
+                    }).collect();
+                self.rows = rows;
+                self.cols = cols;
+                true
+            },
+            MatrixEditorMsg::Edit(i,text) => {
+                self.cells[i] = text;
+                false
+            },
+            MatrixEditorMsg::Push => match self.parse() {
+                Ok(value) => {
+                    ctx.props().on_done.emit(Some(value));
+                    false
+                },
+                Err(e) => {
+                    self.error = Some(e);
+                    true
+                }
+            },
+            MatrixEditorMsg::Cancel => {
+                ctx.props().on_done.emit(None);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::CommOrOpWrapper;
+use crate::calc_unit::ops::{CommOrOp,Command,Op,Arith,Constant,Exp2,Exp,Trig,Native,Convert,ComplexOp,Hyperbolic,Power,Part,NumberOp,PercentOp,TvmOp,TvmVar,FormulaOp,Bitwise,UnitOp,LinalgOp,StatOp,WholeOp,Recall,Arity};
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Category {
+    Stack,
+    WholeStack,
+    Arithmetic,
+    Rounding,
+    NumberTheory,
+    Business,
+    Tvm,
+    Constant,
+    Exponential,
+    Trigonometric,
+    Complex,
+    Bitwise,
+    Units,
+    LinearAlgebra,
+    Statistics,
+    Formula,
+    Conversion
+}
+
+impl Category {
+    pub const ALL: [Category; 17] = [Category::Stack,Category::WholeStack,Category::Arithmetic,Category::Rounding,Category::NumberTheory,Category::Business,Category::Tvm,Category::Constant,Category::Exponential,Category::Trigonometric,Category::Complex,Category::Bitwise,Category::Units,Category::LinearAlgebra,Category::Statistics,Category::Formula,Category::Conversion];
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Stack         => "Stack",
+            Category::WholeStack    => "Whole Stack (or the Top N Levels)",
+            Category::Arithmetic    => "Arithmetic",
+            Category::Rounding      => "Rounding and Integer Parts",
+            Category::NumberTheory  => "Combinatorics and Number Theory",
+            Category::Business      => "Percentages and Business",
+            Category::Tvm           => "Time Value of Money",
+            Category::Constant      => "Constants",
+            Category::Exponential   => "Exponentials and Logarithms",
+            Category::Trigonometric => "Trigonometric and Hyperbolic",
+            Category::Complex       => "Complex Numbers",
+            Category::Bitwise       => "Bitwise",
+            Category::Units         => "Units of Measure",
+            Category::LinearAlgebra => "Vectors and Matrices",
+            Category::Statistics    => "Statistics",
+            Category::Formula       => "Recorded Formula",
+            Category::Conversion    => "Conversions"
+        }
+    }
+}
+
+/// Everything the UI needs to know about one operation.
+pub struct OpDef {
+    /// Token naming the op in keypad layouts.
+    pub id: &'static str,
+    /// Text drawn on the key.
+    pub label: &'static str,
+    pub name: &'static str,
+    pub category: Category,
+    /// `KeyboardEvent.key` that triggers the op, if any.
+    pub shortcut: Option<&'static str>,
+    pub op: CommOrOpWrapper
+}
+
+impl OpDef {
+    pub fn arity(&self) -> Arity {
+        self.op.0.arity()
+    }
+}
+
+macro_rules! op {
+    ($id:literal, $label:literal, $name:literal, $cat:ident, $shortcut:expr, $op:expr) => {
+        OpDef { id: $id, label: $label, name: $name, category: Category::$cat, shortcut: $shortcut, op: CommOrOpWrapper($op) }
+    };
+}
+
+pub static OPS: &[OpDef] = &[
+    op!("drop" ,"Drop" ,"Drop X"                 ,Stack        ,Some("Delete"),CommOrOp::Command(Command::Drop)),
+    op!("dup"  ,"Dup"  ,"Duplicate X"            ,Stack        ,Some("d")     ,CommOrOp::Command(Command::Dup )),
+    op!("swap" ,"Swap" ,"Swap X and Y"           ,Stack        ,Some("x")     ,CommOrOp::Command(Command::Swap)),
+    op!("rev"  ,"Rev"  ,"Reverse the stack, or its top N levels",Stack,Some("r"),CommOrOp::Command(Command::Rev )),
+    op!("lastx"  ,"LastX"  ,"Recall X before the last op"   ,Stack,Some("X"),CommOrOp::Recall(Recall::LastX   )),
+    op!("lastarg","LastArg","Recall the last op's arguments",Stack,Some("A"),CommOrOp::Recall(Recall::LastArgs)),
+    op!("ssum" ,"ΣStk" ,"Sum"                    ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::Sum     ))),
+    op!("sprod","ΠStk" ,"Product"                ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::Product ))),
+    op!("avg"  ,"Avg"  ,"Mean"                   ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::Mean    ))),
+    op!("med"  ,"Med"  ,"Median"                 ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::Median  ))),
+    op!("sort" ,"Sort↑","Sort, largest in X"     ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::SortAsc ))),
+    op!("rsort","Sort↓","Sort, smallest in X"    ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::SortDesc))),
+    op!("min"  ,"Min"  ,"Minimum"                ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::Min     ))),
+    op!("max"  ,"Max"  ,"Maximum"                ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::Max     ))),
+    op!("uniq" ,"Uniq" ,"Drop repeated values"   ,WholeStack   ,None          ,CommOrOp::Native(Native::Whole(WholeOp::Unique  ))),
+    op!("add"  ,"+"    ,"Add"                    ,Arithmetic   ,Some("+")     ,CommOrOp::Op(Op::Arith(Arith::Add))),
+    op!("sub"  ,"-"    ,"Subtract"               ,Arithmetic   ,Some("-")     ,CommOrOp::Op(Op::Arith(Arith::Sub))),
+    op!("mul"  ,"*"    ,"Multiply"               ,Arithmetic   ,Some("*")     ,CommOrOp::Op(Op::Arith(Arith::Mul))),
+    op!("div"  ,"/"    ,"Divide"                 ,Arithmetic   ,Some("/")     ,CommOrOp::Op(Op::Arith(Arith::Div))),
+    op!("floor","Floor","Round down"             ,Rounding     ,None          ,CommOrOp::Native(Native::Part(Part::Floor ))),
+    op!("ceil" ,"Ceil" ,"Round up"               ,Rounding     ,None          ,CommOrOp::Native(Native::Part(Part::Ceil  ))),
+    op!("rnd"  ,"RND"  ,"Round Y to X decimal places",Rounding ,None          ,CommOrOp::Native(Native::Part(Part::Round ))),
+    op!("ip"   ,"IP"   ,"Integer part"           ,Rounding     ,None          ,CommOrOp::Native(Native::Part(Part::Trunc ))),
+    op!("fp"   ,"FP"   ,"Fractional part"        ,Rounding     ,None          ,CommOrOp::Native(Native::Part(Part::Frac  ))),
+    op!("sign" ,"Sign" ,"Sign"                   ,Rounding     ,None          ,CommOrOp::Native(Native::Part(Part::Sign  ))),
+    op!("mod"  ,"Mod"  ,"Y modulo X"             ,Rounding     ,None          ,CommOrOp::Native(Native::Part(Part::Mod   ))),
+    op!("idiv" ,"Div"  ,"Y divided by X, rounded down",Rounding,Some("\\")    ,CommOrOp::Native(Native::Part(Part::IntDiv))),
+    op!("fact" ,"x!"   ,"Factorial"              ,NumberTheory ,Some("!")     ,CommOrOp::Native(Native::NumberTheory(NumberOp::Factorial))),
+    op!("gamma","Γ"    ,"Gamma function"         ,NumberTheory ,None          ,CommOrOp::Native(Native::NumberTheory(NumberOp::Gamma    ))),
+    op!("ncr"  ,"nCr"  ,"Combinations of X out of Y",NumberTheory,None        ,CommOrOp::Native(Native::NumberTheory(NumberOp::Comb     ))),
+    op!("npr"  ,"nPr"  ,"Permutations of X out of Y",NumberTheory,None        ,CommOrOp::Native(Native::NumberTheory(NumberOp::Perm     ))),
+    op!("gcd"  ,"GCD"  ,"Greatest common divisor",NumberTheory ,None          ,CommOrOp::Native(Native::NumberTheory(NumberOp::Gcd      ))),
+    op!("lcm"  ,"LCM"  ,"Least common multiple"  ,NumberTheory ,None          ,CommOrOp::Native(Native::NumberTheory(NumberOp::Lcm      ))),
+    op!("prime","Prime?","1 if X is prime, 0 if not",NumberTheory,None        ,CommOrOp::Native(Native::NumberTheory(NumberOp::IsPrime  ))),
+    op!("factor","Factor","Prime factors of X"   ,NumberTheory ,None          ,CommOrOp::Native(Native::NumberTheory(NumberOp::Factor   ))),
+    op!("modpow","PowMod","Z to the power of Y, modulo X",NumberTheory,None   ,CommOrOp::Native(Native::NumberTheory(NumberOp::ModPow   ))),
+    op!("pct"  ,"%"    ,"X percent of Y, keeping Y",Business   ,Some("%")     ,CommOrOp::Native(Native::Percent(PercentOp::Percent ))),
+    op!("dpct" ,"Δ%"   ,"Percent change from Y to X",Business  ,None          ,CommOrOp::Native(Native::Percent(PercentOp::Change  ))),
+    op!("pctt" ,"%T"   ,"X as a percentage of the total Y",Business,None      ,CommOrOp::Native(Native::Percent(PercentOp::OfTotal ))),
+    op!("mu"   ,"MU"   ,"Price from cost Y and markup X%",Business,None       ,CommOrOp::Native(Native::Percent(PercentOp::Markup  ))),
+    op!("mgn"  ,"MGN"  ,"Price from cost Y and margin X%",Business,None       ,CommOrOp::Native(Native::Percent(PercentOp::Margin  ))),
+    op!("pmgn" ,"MGN%" ,"Margin of price X over cost Y",Business,None         ,CommOrOp::Native(Native::Percent(PercentOp::MarginOf))),
+    op!("tvm.n"   ,"N"    ,"Store X as the number of payments",Tvm,None,CommOrOp::Native(Native::Tvm(TvmOp::Store(TvmVar::N  )))),
+    op!("tvm.i"   ,"I/YR" ,"Store X as the annual interest rate",Tvm,None,CommOrOp::Native(Native::Tvm(TvmOp::Store(TvmVar::IYr)))),
+    op!("tvm.pv"  ,"PV"   ,"Store X as the present value",Tvm ,None,CommOrOp::Native(Native::Tvm(TvmOp::Store(TvmVar::Pv )))),
+    op!("tvm.pmt" ,"PMT"  ,"Store X as the payment"      ,Tvm ,None,CommOrOp::Native(Native::Tvm(TvmOp::Store(TvmVar::Pmt)))),
+    op!("tvm.fv"  ,"FV"   ,"Store X as the future value" ,Tvm ,None,CommOrOp::Native(Native::Tvm(TvmOp::Store(TvmVar::Fv )))),
+    op!("tvm.pyr" ,"P/YR" ,"Store X as payments per year",Tvm ,None,CommOrOp::Native(Native::Tvm(TvmOp::Store(TvmVar::PYr)))),
+    op!("tvm.n?"  ,"N?"   ,"Solve for the number of payments",Tvm,None,CommOrOp::Native(Native::Tvm(TvmOp::Solve(TvmVar::N  )))),
+    op!("tvm.i?"  ,"I/YR?","Solve for the annual interest rate",Tvm,None,CommOrOp::Native(Native::Tvm(TvmOp::Solve(TvmVar::IYr)))),
+    op!("tvm.pv?" ,"PV?"  ,"Solve for the present value" ,Tvm ,None,CommOrOp::Native(Native::Tvm(TvmOp::Solve(TvmVar::Pv )))),
+    op!("tvm.pmt?","PMT?" ,"Solve for the payment"       ,Tvm ,None,CommOrOp::Native(Native::Tvm(TvmOp::Solve(TvmVar::Pmt)))),
+    op!("tvm.fv?" ,"FV?"  ,"Solve for the future value"  ,Tvm ,None,CommOrOp::Native(Native::Tvm(TvmOp::Solve(TvmVar::Fv )))),
+    op!("tvm.beg" ,"BEG/END","Payments at the start or end of each period",Tvm,None,CommOrOp::Native(Native::Tvm(TvmOp::ToggleBegin))),
+    op!("tvm.clr" ,"CLR TVM","Clear N, I/YR, PV, PMT and FV",Tvm,None,CommOrOp::Native(Native::Tvm(TvmOp::Clear))),
+    op!("pi"   ,"Pi"   ,"Push pi"                ,Constant     ,Some("p")     ,CommOrOp::Op(Op::Constant(Constant::Pi ))),
+    op!("e"    ,"E"    ,"Push Euler's number"    ,Constant     ,Some("e")     ,CommOrOp::Op(Op::Constant(Constant::E  ))),
+    op!("inf"  ,"Inf"  ,"Push infinity"          ,Constant     ,Some("i")     ,CommOrOp::Op(Op::Constant(Constant::Inf))),
+    op!("pow"  ,"Pow"  ,"Y to the power of X"    ,Exponential  ,Some("^")     ,CommOrOp::Op(Op::Exp2(Exp2::Pow  ))),
+    op!("logn" ,"LogN" ,"Logarithm of Y base X"  ,Exponential  ,Some("L")     ,CommOrOp::Op(Op::Exp2(Exp2::LogN ))),
+    op!("rootn","RootN","X-th root of Y"         ,Exponential  ,Some("R")     ,CommOrOp::Op(Op::Exp2(Exp2::RootN))),
+    op!("log10","Log10","Common logarithm"       ,Exponential  ,Some("l")     ,CommOrOp::Op(Op::Exp(Exp::Log10))),
+    op!("log2" ,"Log2" ,"Binary logarithm"       ,Exponential  ,None          ,CommOrOp::Op(Op::Exp(Exp::Log2 ))),
+    op!("ln"   ,"LogE" ,"Natural logarithm"      ,Exponential  ,Some("n")     ,CommOrOp::Op(Op::Exp(Exp::LogE ))),
+    op!("sqrt" ,"Root2","Square root"            ,Exponential  ,Some("q")     ,CommOrOp::Op(Op::Exp(Exp::Root2))),
+    op!("exp"  ,"eˣ"   ,"Natural exponential"    ,Exponential  ,None          ,CommOrOp::Native(Native::Power(Power::E  ))),
+    op!("exp10","10ˣ"  ,"Ten to the power of X"  ,Exponential  ,None          ,CommOrOp::Native(Native::Power(Power::Ten))),
+    op!("exp2" ,"2ˣ"   ,"Two to the power of X"  ,Exponential  ,None          ,CommOrOp::Native(Native::Power(Power::Two))),
+    op!("sin"  ,"Sin"  ,"Sine"                   ,Trigonometric,Some("s")     ,CommOrOp::Op(Op::Trig(Trig::Sin ))),
+    op!("cos"  ,"Cos"  ,"Cosine"                 ,Trigonometric,Some("c")     ,CommOrOp::Op(Op::Trig(Trig::Cos ))),
+    op!("tan"  ,"Tan"  ,"Tangent"                ,Trigonometric,Some("t")     ,CommOrOp::Op(Op::Trig(Trig::Tan ))),
+    op!("asin" ,"ASin" ,"Arcsine"                ,Trigonometric,Some("S")     ,CommOrOp::Op(Op::Trig(Trig::ASin))),
+    op!("acos" ,"ACos" ,"Arccosine"              ,Trigonometric,Some("C")     ,CommOrOp::Op(Op::Trig(Trig::ACos))),
+    op!("atan" ,"ATan" ,"Arctangent"             ,Trigonometric,Some("T")     ,CommOrOp::Op(Op::Trig(Trig::ATan))),
+    op!("sinh" ,"Sinh" ,"Hyperbolic sine"        ,Trigonometric,None          ,CommOrOp::Native(Native::Hyperbolic(Hyperbolic::Sinh ))),
+    op!("cosh" ,"Cosh" ,"Hyperbolic cosine"      ,Trigonometric,None          ,CommOrOp::Native(Native::Hyperbolic(Hyperbolic::Cosh ))),
+    op!("tanh" ,"Tanh" ,"Hyperbolic tangent"     ,Trigonometric,None          ,CommOrOp::Native(Native::Hyperbolic(Hyperbolic::Tanh ))),
+    op!("asinh","ASinh","Inverse hyperbolic sine",Trigonometric,None          ,CommOrOp::Native(Native::Hyperbolic(Hyperbolic::ASinh))),
+    op!("acosh","ACosh","Inverse hyperbolic cosine",Trigonometric,None        ,CommOrOp::Native(Native::Hyperbolic(Hyperbolic::ACosh))),
+    op!("atanh","ATanh","Inverse hyperbolic tangent",Trigonometric,None       ,CommOrOp::Native(Native::Hyperbolic(Hyperbolic::ATanh))),
+    op!("conj" ,"Conj" ,"Complex conjugate"      ,Complex      ,None          ,CommOrOp::Native(Native::Complex(ComplexOp::Conj))),
+    op!("re"   ,"Re"   ,"Real part"              ,Complex      ,None          ,CommOrOp::Native(Native::Complex(ComplexOp::Re  ))),
+    op!("im"   ,"Im"   ,"Imaginary part"         ,Complex      ,None          ,CommOrOp::Native(Native::Complex(ComplexOp::Im  ))),
+    op!("abs"  ,"Abs"  ,"Absolute value"         ,Complex      ,Some("|")     ,CommOrOp::Native(Native::Complex(ComplexOp::Abs ))),
+    op!("arg"  ,"Arg"  ,"Argument (angle)"       ,Complex      ,None          ,CommOrOp::Native(Native::Complex(ComplexOp::Arg ))),
+    op!("and"  ,"AND"  ,"Bitwise and"            ,Bitwise      ,Some("&")     ,CommOrOp::Native(Native::Bitwise(Bitwise::And ))),
+    op!("or"   ,"OR"   ,"Bitwise or"             ,Bitwise      ,None          ,CommOrOp::Native(Native::Bitwise(Bitwise::Or  ))),
+    op!("xor"  ,"XOR"  ,"Bitwise exclusive or"   ,Bitwise      ,None          ,CommOrOp::Native(Native::Bitwise(Bitwise::Xor ))),
+    op!("not"  ,"NOT"  ,"Bitwise complement"     ,Bitwise      ,Some("~")     ,CommOrOp::Native(Native::Bitwise(Bitwise::Not ))),
+    op!("shl"  ,"<<"   ,"Shift Y left by X bits" ,Bitwise      ,Some("<")     ,CommOrOp::Native(Native::Bitwise(Bitwise::Shl ))),
+    op!("shr"  ,">>"   ,"Shift Y right by X bits",Bitwise      ,Some(">")     ,CommOrOp::Native(Native::Bitwise(Bitwise::Shr ))),
+    op!("rotl" ,"RL"   ,"Rotate Y left by X bits",Bitwise      ,None          ,CommOrOp::Native(Native::Bitwise(Bitwise::RotL))),
+    op!("rotr" ,"RR"   ,"Rotate Y right by X bits",Bitwise     ,None          ,CommOrOp::Native(Native::Bitwise(Bitwise::RotR))),
+    op!("conv" ,"CONV" ,"Convert Y into X's unit",Units        ,None          ,CommOrOp::Native(Native::Units(UnitOp::Convert))),
+    op!("uval" ,"UVAL" ,"Drop X's unit"          ,Units        ,None          ,CommOrOp::Native(Native::Units(UnitOp::Strip  ))),
+    op!("dot"  ,"Dot"  ,"Dot product"            ,LinearAlgebra,None          ,CommOrOp::Native(Native::Linalg(LinalgOp::Dot      ))),
+    op!("cross","Cross","Cross product"          ,LinearAlgebra,None          ,CommOrOp::Native(Native::Linalg(LinalgOp::Cross    ))),
+    op!("trn"  ,"Trn"  ,"Transpose"              ,LinearAlgebra,None          ,CommOrOp::Native(Native::Linalg(LinalgOp::Transpose))),
+    op!("det"  ,"Det"  ,"Determinant"            ,LinearAlgebra,None          ,CommOrOp::Native(Native::Linalg(LinalgOp::Det      ))),
+    op!("inv"  ,"Inv"  ,"Matrix inverse"         ,LinearAlgebra,None          ,CommOrOp::Native(Native::Linalg(LinalgOp::Inverse  ))),
+    op!("solve","Solve","Solve Y·x = X for x"    ,LinearAlgebra,None          ,CommOrOp::Native(Native::Linalg(LinalgOp::Solve    ))),
+    op!("s+"   ,"Σ+"   ,"Accumulate x"           ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Add       ))),
+    op!("s+xy" ,"Σ+xy" ,"Accumulate the pair (X, Y)",Statistics,None          ,CommOrOp::Native(Native::Stats(StatOp::AddPair   ))),
+    op!("s-"   ,"Σ−"   ,"Remove x"               ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Remove    ))),
+    op!("s-xy" ,"Σ−xy" ,"Remove the pair (X, Y)" ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::RemovePair))),
+    op!("n"    ,"n"    ,"Number of points"       ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Count     ))),
+    op!("mean" ,"x̄"    ,"Mean"                   ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Mean      ))),
+    op!("sdev" ,"s"    ,"Sample standard deviation",Statistics ,None          ,CommOrOp::Native(Native::Stats(StatOp::SDev      ))),
+    op!("psdev","σ"    ,"Population standard deviation",Statistics,None       ,CommOrOp::Native(Native::Stats(StatOp::PSDev     ))),
+    op!("slope","m"    ,"Regression slope"       ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Slope     ))),
+    op!("icept","b"    ,"Regression intercept"   ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Intercept ))),
+    op!("corr" ,"r"    ,"Correlation coefficient",Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Corr      ))),
+    op!("yhat" ,"ŷ"    ,"Forecast y at x = X"    ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::ForecastY ))),
+    op!("xhat" ,"x̂"    ,"Forecast x at y = X"    ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::ForecastX ))),
+    op!("sclr" ,"ΣCLR" ,"Clear statistics"       ,Statistics   ,None          ,CommOrOp::Native(Native::Stats(StatOp::Clear     ))),
+    op!("root" ,"Root" ,"Root of the formula near X",Formula  ,None          ,CommOrOp::Native(Native::Formula(FormulaOp::Solve       ))),
+    op!("root2","Root⇔","Root of the formula between Y and X",Formula,None    ,CommOrOp::Native(Native::Formula(FormulaOp::SolveBetween))),
+    op!("integ","∫"    ,"Integral of the formula from Z to Y, to within X",Formula,None,CommOrOp::Native(Native::Formula(FormulaOp::Integrate ))),
+    op!("deriv","d/dx" ,"Derivative of the formula at X",Formula ,None          ,CommOrOp::Native(Native::Formula(FormulaOp::Derivative))),
+    op!("frac" ,"a b/c","Toggle fraction"        ,Conversion   ,Some("f")     ,CommOrOp::Native(Native::Convert(Convert::ToggleFraction)))
+];
+
+pub fn by_id(id: &str) -> Option<&'static OpDef> {
+    OPS.iter().find(|def| def.id == id)
+}
+
+pub fn by_shortcut(key: &str) -> Option<&'static OpDef> {
+    OPS.iter().find(|def| def.shortcut == Some(key))
+}
+
+/// The entry for `op`. Conversions to a unit picked in the unit picker have
+/// none.
+pub fn by_op(op: CommOrOpWrapper) -> Option<&'static OpDef> {
+    OPS.iter().find(|def| def.op == op)
+}
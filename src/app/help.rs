@@ -0,0 +1,57 @@
+use yew::prelude::*;
+use stylist::css;
+use super::Route;
+use super::calculator::registry::{self,Category};
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn, error};
+
+#[derive(PartialEq,Properties)]
+pub struct HelpProps {
+    pub visible: bool
+}
+
+/// Reference of every operation, generated from the op registry.
+#[function_component(Help)]
+pub fn help(props: &HelpProps) -> Html {
+    if !props.visible {
+        return html!{};
+    }
+    let sections = Category::ALL.iter().map(|&category| {
+        let rows = registry::OPS.iter()
+            .filter(|def| def.category == category)
+            .map(|def| html!{
+                <tr>
+                    <td> { def.label } </td>
+                    <td> { def.name } </td>
+                    <td> { def.arity().to_string() } </td>
+                    <td> { def.shortcut.unwrap_or("") } </td>
+                </tr>
+            }).collect::<Html>();
+        html!{
+            <details>
+                <summary> { category.name() } </summary>
+                <table>
+                    <tr> <th> { "Key" } </th> <th> { "Operation" } </th> <th> { "Consumes" } </th> <th> { "Shortcut" } </th> </tr>
+                    { rows }
+                </table>
+            </details>
+        }
+    }).collect::<Html>();
+    html!{
+        <div class={{ css!{
+            display: grid;
+            grid:   "a b b b b" 1fr
+                    ". b b b b" 15fr;
+        }}}>
+            <button class={{css!{grid-area: a;}}} onclick={ |_| Route::Calc.navigate() }> { "Return to Calculator" } </button> <br/>
+            <div class={{css!{grid-area: b; overflow-y: scroll;}}}>
+                <p> { "Help" } </p>
+                <p> { "Digits, '.', Backspace and Enter work from the keyboard as well." } </p>
+                <p> { "Whole stack operations take only the top N levels when N is typed first. 'z' undoes the last step." } </p>
+                <p> { "Rec records the keys that follow as a formula of the value in X, until it is pressed again. It is worked out in the number mode it was recorded in. Root then solves the formula for zero. ∫ and d/dx integrate and differentiate it, leaving an estimate of their error in Y." } </p>
+                <p> { "Plot f and Plot ⋰ draw the formula or the stack. Drag to pan, use the wheel or +/− to zoom, and tap to trace; Push puts the traced y and x in Y and X." } </p>
+                { sections }
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,441 @@
+use yew::prelude::*;
+use stylist::css;
+use yew::context::ContextHandle;
+use wasm_bindgen::{JsCast,JsValue};
+use web_sys::{HtmlCanvasElement,CanvasRenderingContext2d};
+use crate::utils::bus::{Bus,Subscription};
+use crate::utils::Ignore;
+use crate::calc_unit::Value;
+use num_bigint::BigInt;
+use super::{Route,SharedConfig,ColorTheme,PlotSeries,PushPoint};
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn, error};
+
+/// Colors the series are drawn in, in turn.
+const PALETTE: [&str; 5] = ["#1f77b4","#d62728","#2ca02c","#ff7f0e","#9467bd"];
+/// Pixels the pointer may move between press and release and still count
+/// as a click.
+const CLICK_SLOP: f64 = 4.0;
+/// Pixels between the points a formula is sampled at.
+const SAMPLE_SPACING: usize = 2;
+
+pub enum PlotMsg {
+    Add(PlotSeries),
+    ConfigChanged(SharedConfig),
+    /// Pointer pressed, moved or released, in canvas pixels.
+    Down(f64,f64),
+    Move(f64,f64),
+    Up(f64,f64),
+    Leave,
+    /// Wheel turned by a delta, over a point in canvas pixels.
+    Wheel(f64,f64,f64),
+    /// Scales the view about its center.
+    Zoom(f64),
+    Fit,
+    Clear,
+    /// Moves the trace cursor left (-1) or right (1).
+    TraceStep(i32),
+    /// Moves the trace cursor onto the next series.
+    TraceSeries,
+    PushTrace
+}
+
+#[derive(PartialEq,Properties)]
+pub struct PlotProps {
+    pub bus: Bus,
+    pub visible: bool
+}
+
+/// The part of the plane on screen.
+#[derive(Clone,Copy,PartialEq)]
+struct View {
+    x0: f64,
+    x1: f64,
+    y0: f64,
+    y1: f64
+}
+
+impl View {
+    /// Scales the view by `factor` about the point (`cx`, `cy`), which stays
+    /// where it is on screen.
+    fn zoom(self, factor: f64, (cx,cy): (f64,f64)) -> View {
+        View {
+            x0: cx + (self.x0 - cx) * factor,
+            x1: cx + (self.x1 - cx) * factor,
+            y0: cy + (self.y0 - cy) * factor,
+            y1: cy + (self.y1 - cy) * factor
+        }
+    }
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View { x0: -10.0, x1: 10.0, y0: -10.0, y1: 10.0 }
+    }
+}
+
+/// Round-numbered positions between `lo` and `hi`, about eight of them, and
+/// their spacing.
+fn ticks(lo: f64, hi: f64) -> (Vec<f64>,f64) {
+    let rough = (hi - lo) / 8.0;
+    if !(rough > 0.0 && rough.is_finite()) {
+        return (vec![],0.0);
+    }
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0,2.0,5.0,10.0].iter().map(|m| m * magnitude).find(|&s| s >= rough).unwrap_or(10.0 * magnitude);
+    let (first,last) = ((lo / step).ceil() as i64,(hi / step).floor() as i64);
+    ((first..=last).map(|k| k as f64 * step).collect(),step)
+}
+
+/// The point of `series` the trace cursor sits on when it is at `x`.
+fn trace_point(series: &PlotSeries, x: f64) -> Option<(f64,f64)> {
+    match series {
+        PlotSeries::Formula(f,_) => f.eval(x).ok().map(|y| (x,y)),
+        PlotSeries::Points(points) => points.iter().copied().min_by(|a,b| (a.0 - x).abs().total_cmp(&(b.0 - x).abs()))
+    }
+}
+
+pub struct Plot {
+    series: Vec<PlotSeries>,
+    view: View,
+    /// Canvas size in pixels, as of the last render.
+    size: (f64,f64),
+    /// Where a press started and the view at the time, while it lasts.
+    drag: Option<((f64,f64),View)>,
+    /// Whether the current press has moved far enough to be a drag.
+    dragged: bool,
+    /// The trace cursor: which series, and the x it is at.
+    trace: Option<(usize,f64)>,
+    canvas: NodeRef,
+    config: SharedConfig,
+    _config_handle: ContextHandle<SharedConfig>,
+    _subscriptions: Vec<Subscription>
+}
+
+impl Plot {
+    fn to_world(&self, (px,py): (f64,f64)) -> (f64,f64) {
+        let View { x0, x1, y0, y1 } = self.view;
+        let (w,h) = self.size;
+        (x0 + px / w * (x1 - x0),y1 - py / h * (y1 - y0))
+    }
+    fn to_pixel(&self, (x,y): (f64,f64)) -> (f64,f64) {
+        let View { x0, x1, y0, y1 } = self.view;
+        let (w,h) = self.size;
+        ((x - x0) / (x1 - x0) * w,(y1 - y) / (y1 - y0) * h)
+    }
+    /// A tick value in the user's number format, as an integer where it is
+    /// one so it follows the display base. Integers are written with a sign
+    /// rather than as a programmer word, so negative ticks read as such.
+    fn tick_label(&self, t: f64, step: f64) -> String {
+        let decimals = (-step.log10().floor()).max(0.0) as usize;
+        let t = format!("{t:.decimals$}").parse().unwrap_or(t);
+        let v = match t.fract() == 0.0 && t.abs() < 1e15 {
+            true => Value::BigInt(BigInt::from(t as i64)),
+            false => Value::Float(t)
+        };
+        v.format(&self.config.number_format)
+    }
+    /// Shows every point and, over that width, most of every formula. The
+    /// highest and lowest twentieth of formula values are left out, so
+    /// poles do not flatten the rest.
+    fn fit(&mut self) {
+        let points = self.series.iter().filter_map(|s| match s {
+            PlotSeries::Points(points) => Some(points),
+            _ => None
+        }).flatten().copied().collect::<Vec<_>>();
+        let (x0,x1) = match points.is_empty() {
+            true => (self.view.x0,self.view.x1),
+            false => points.iter().fold((f64::INFINITY,f64::NEG_INFINITY),|(lo,hi),p| (lo.min(p.0),hi.max(p.0)))
+        };
+        let (x0,x1) = if x0 < x1 { (x0,x1) } else { (x0 - 1.0,x1 + 1.0) };
+        let mut ys = self.series.iter().filter_map(|s| match s {
+            PlotSeries::Formula(f,_) => Some(f),
+            _ => None
+        }).flat_map(|f| (0..=100).filter_map(move |i| f.eval(x0 + (x1 - x0) * i as f64 / 100.0).ok())).collect::<Vec<_>>();
+        ys.sort_by(f64::total_cmp);
+        let trim = ys.len() / 20;
+        let ys = ys[trim..ys.len() - trim].iter().chain(points.iter().map(|p| &p.1));
+        let (y0,y1) = ys.fold((f64::INFINITY,f64::NEG_INFINITY),|(lo,hi),&y| (lo.min(y),hi.max(y)));
+        let (y0,y1) = match (y0,y1) {
+            (y0,y1) if y0 < y1 => (y0,y1),
+            (y,_) if y.is_finite() => (y - 1.0,y + 1.0),
+            _ => (View::default().y0,View::default().y1)
+        };
+        let (dx,dy) = ((x1 - x0) / 20.0,(y1 - y0) / 20.0);
+        self.view = View { x0: x0 - dx, x1: x1 + dx, y0: y0 - dy, y1: y1 + dy };
+    }
+    /// Puts the trace cursor on series `i` at `x`, snapping to a point of a
+    /// data series.
+    fn set_trace(&mut self, i: usize, x: f64) {
+        let x = match &self.series[i] {
+            PlotSeries::Points(_) => trace_point(&self.series[i],x).map_or(x,|p| p.0),
+            PlotSeries::Formula(..) => x
+        };
+        self.trace = Some((i,x));
+    }
+    fn trace_step(&mut self, dir: i32) {
+        let (i,x) = match self.trace {
+            Some(trace) => trace,
+            None => return
+        };
+        let x = match &self.series[i] {
+            PlotSeries::Formula(..) => x + dir as f64 * (self.view.x1 - self.view.x0) / 100.0,
+            PlotSeries::Points(points) => {
+                let xs = points.iter().map(|p| p.0);
+                let next = match dir > 0 {
+                    true => xs.filter(|&px| px > x).min_by(f64::total_cmp),
+                    false => xs.filter(|&px| px < x).max_by(f64::total_cmp)
+                };
+                next.unwrap_or(x)
+            }
+        };
+        self.trace = Some((i,x));
+    }
+    fn draw(&self, cx: &CanvasRenderingContext2d) {
+        let (w,h) = self.size;
+        let View { x0, x1, y0, y1 } = self.view;
+        let ink = match self.config.color_theme {
+            ColorTheme::Dark => "white",
+            ColorTheme::Light => "black"
+        };
+        cx.clear_rect(0.0,0.0,w,h);
+        cx.set_line_width(1.0);
+        cx.set_font("12px sans-serif");
+        cx.set_fill_style(&JsValue::from_str(ink));
+        // Tick labels run along the axes, or along the edges once an axis
+        // is off screen.
+        let (ox,oy) = self.to_pixel((0.0,0.0));
+        let (label_x,label_y) = (ox.clamp(0.0,w - 40.0),oy.clamp(0.0,h - 14.0));
+        cx.set_stroke_style(&JsValue::from_str("rgba(128,128,128,0.3)"));
+        cx.begin_path();
+        let (xs,step) = ticks(x0,x1);
+        for t in xs {
+            let px = self.to_pixel((t,0.0)).0;
+            cx.move_to(px,0.0);
+            cx.line_to(px,h);
+            cx.fill_text(&self.tick_label(t,step),px + 2.0,label_y + 12.0).ignore();
+        }
+        let (ys,step) = ticks(y0,y1);
+        for t in ys {
+            let py = self.to_pixel((0.0,t)).1;
+            cx.move_to(0.0,py);
+            cx.line_to(w,py);
+            cx.fill_text(&self.tick_label(t,step),label_x + 2.0,py - 2.0).ignore();
+        }
+        cx.stroke();
+        cx.set_stroke_style(&JsValue::from_str("gray"));
+        cx.begin_path();
+        cx.move_to(ox,0.0);
+        cx.line_to(ox,h);
+        cx.move_to(0.0,oy);
+        cx.line_to(w,oy);
+        cx.stroke();
+        for (i,series) in self.series.iter().enumerate() {
+            let color = JsValue::from_str(PALETTE[i % PALETTE.len()]);
+            cx.set_stroke_style(&color);
+            cx.set_fill_style(&color);
+            match series {
+                PlotSeries::Formula(f,_) => {
+                    cx.begin_path();
+                    let mut pen_down = false;
+                    for px in (0..=w as usize).step_by(SAMPLE_SPACING) {
+                        let x = self.to_world((px as f64,0.0)).0;
+                        // Leaves gaps where the formula fails or shoots off.
+                        match f.eval(x).map(|y| self.to_pixel((x,y)).1) {
+                            Ok(py) if py.abs() < 1e5 => {
+                                match pen_down {
+                                    true => cx.line_to(px as f64,py),
+                                    false => cx.move_to(px as f64,py)
+                                }
+                                pen_down = true;
+                            },
+                            _ => pen_down = false
+                        }
+                    }
+                    cx.stroke();
+                },
+                PlotSeries::Points(points) => for &p in points {
+                    let (px,py) = self.to_pixel(p);
+                    cx.begin_path();
+                    cx.arc(px,py,3.0,0.0,std::f64::consts::TAU).ignore();
+                    cx.fill();
+                }
+            }
+        }
+        if let Some((x,y)) = self.trace.and_then(|(i,x)| trace_point(&self.series[i],x)) {
+            let (px,py) = self.to_pixel((x,y));
+            cx.set_stroke_style(&JsValue::from_str(ink));
+            cx.begin_path();
+            cx.arc(px,py,6.0,0.0,std::f64::consts::TAU).ignore();
+            cx.move_to(px,py - 10.0);
+            cx.line_to(px,py + 10.0);
+            cx.stroke();
+        }
+    }
+}
+
+impl Component for Plot {
+    type Message = PlotMsg;
+    type Properties = PlotProps;
+    fn create(ctx: &Context<Self>) -> Self {
+        let (config, _config_handle) = ctx.link()
+            .context::<SharedConfig>(ctx.link().callback(PlotMsg::ConfigChanged))
+            .expect("Plot must be rendered inside a SharedConfig provider");
+        let _subscriptions = vec![
+            ctx.props().bus.subscribe(ctx.link().callback(PlotMsg::Add))
+        ];
+        Plot {
+            series: vec![],
+            view: View::default(),
+            size: (1.0,1.0),
+            drag: None,
+            dragged: false,
+            trace: None,
+            canvas: NodeRef::default(),
+            config,
+            _config_handle,
+            _subscriptions
+        }
+    }
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if !ctx.props().visible {
+            return html!{};
+        }
+        let legend = self.series.iter().enumerate().map(|(i,s)| {
+            let label = match s {
+                PlotSeries::Formula(_,label) => label.as_str(),
+                PlotSeries::Points(_) => "Stack data"
+            };
+            html!{ <p style={ format!("color: {}; margin: 0;",PALETTE[i % PALETTE.len()]) }> { label } </p> }
+        }).collect::<Html>();
+        let readout = match self.trace.and_then(|(i,x)| trace_point(&self.series[i],x)) {
+            Some((x,y)) => {
+                let format = &self.config.number_format;
+                format!("x = {}   y = {}",Value::Float(x).format(format),Value::Float(y).format(format))
+            },
+            None => "Tap the plot to trace".to_string()
+        };
+        let pointer = |e: &PointerEvent| (e.offset_x() as f64,e.offset_y() as f64);
+        html!{
+            <div class={{ css!{
+                width: 100%;
+                height: 100%;
+                display: grid;
+                grid:   "a c c c c" 2fr
+                        "p p p p p" 12fr
+                        "t t t t t" 1fr
+                        "k k k k k" 1fr;
+            }}}>
+                <button class={{css!{grid-area: a;}}} onclick={ |_| Route::Calc.navigate() }> { "Return to Calculator" } </button>
+                <div class={{css!{grid-area: c; overflow-y: auto; font-size: 3vh;}}}> { legend } </div>
+                <canvas ref={ self.canvas.clone() } class={{css!{grid-area: p; width: 100%; height: 100%; touch-action: none;}}}
+                    onpointerdown={ ctx.link().callback(move |e: PointerEvent| { let (x,y) = pointer(&e); PlotMsg::Down(x,y) }) }
+                    onpointermove={ ctx.link().callback(move |e: PointerEvent| { let (x,y) = pointer(&e); PlotMsg::Move(x,y) }) }
+                    onpointerup={ ctx.link().callback(move |e: PointerEvent| { let (x,y) = pointer(&e); PlotMsg::Up(x,y) }) }
+                    onpointerleave={ ctx.link().callback(|_| PlotMsg::Leave) }
+                    onwheel={ ctx.link().callback(|e: WheelEvent| PlotMsg::Wheel(e.offset_x() as f64,e.offset_y() as f64,e.delta_y())) }>
+                </canvas>
+                <div class={{css!{grid-area: t; overflow-x: hidden; white-space: nowrap;}}}> { readout } </div>
+                <div class={{css!{grid-area: k; display: grid; grid-auto-flow: column;}}}>
+                    <button title="Zoom out" onclick={ ctx.link().callback(|_| PlotMsg::Zoom(1.25)) }> { "−" } </button>
+                    <button title="Zoom in" onclick={ ctx.link().callback(|_| PlotMsg::Zoom(0.8)) }> { "+" } </button>
+                    <button title="Fit everything in view" onclick={ ctx.link().callback(|_| PlotMsg::Fit) }> { "Fit" } </button>
+                    <button title="Trace left" onclick={ ctx.link().callback(|_| PlotMsg::TraceStep(-1)) }> { "◀" } </button>
+                    <button title="Trace right" onclick={ ctx.link().callback(|_| PlotMsg::TraceStep(1)) }> { "▶" } </button>
+                    <button title="Trace the next series" onclick={ ctx.link().callback(|_| PlotMsg::TraceSeries) }> { "Next" } </button>
+                    <button title="Push the traced x and y onto the stack" disabled={ self.trace.is_none() } onclick={ ctx.link().callback(|_| PlotMsg::PushTrace) }> { "Push" } </button>
+                    <button title="Remove everything plotted" onclick={ ctx.link().callback(|_| PlotMsg::Clear) }> { "Clear" } </button>
+                </div>
+            </div>
+        }
+    }
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            PlotMsg::Add(series) => {
+                self.series.push(series);
+                self.trace = None;
+                self.fit();
+            },
+            PlotMsg::ConfigChanged(config) => {
+                let changed = self.config.number_format != config.number_format
+                    || self.config.color_theme != config.color_theme;
+                self.config = config;
+                return changed && ctx.props().visible;
+            },
+            PlotMsg::Down(x,y) => {
+                self.drag = Some(((x,y),self.view));
+                self.dragged = false;
+                return false;
+            },
+            PlotMsg::Move(x,y) => {
+                let ((sx,sy),view) = match self.drag {
+                    Some(drag) => drag,
+                    None => return false
+                };
+                let (dx,dy) = (x - sx,y - sy);
+                self.dragged |= dx.hypot(dy) > CLICK_SLOP;
+                if !self.dragged {
+                    return false;
+                }
+                let (w,h) = self.size;
+                let shift_x = dx / w * (view.x1 - view.x0);
+                let shift_y = dy / h * (view.y1 - view.y0);
+                self.view = View { x0: view.x0 - shift_x, x1: view.x1 - shift_x, y0: view.y0 + shift_y, y1: view.y1 + shift_y };
+            },
+            PlotMsg::Up(x,y) => {
+                if self.drag.take().is_none() || self.dragged || self.series.is_empty() {
+                    return false;
+                }
+                let i = self.trace.map_or(0,|(i,_)| i);
+                self.set_trace(i,self.to_world((x,y)).0);
+            },
+            PlotMsg::Leave => {
+                self.drag = None;
+                return false;
+            },
+            PlotMsg::Wheel(x,y,delta) => {
+                let factor = if delta > 0.0 { 1.25 } else { 0.8 };
+                self.view = self.view.zoom(factor,self.to_world((x,y)));
+            },
+            PlotMsg::Zoom(factor) => {
+                let View { x0, x1, y0, y1 } = self.view;
+                self.view = self.view.zoom(factor,((x0 + x1) / 2.0,(y0 + y1) / 2.0));
+            },
+            PlotMsg::Fit => self.fit(),
+            PlotMsg::Clear => {
+                self.series.clear();
+                self.trace = None;
+            },
+            PlotMsg::TraceStep(dir) => self.trace_step(dir),
+            PlotMsg::TraceSeries => if let Some((i,x)) = self.trace {
+                self.set_trace((i + 1) % self.series.len(),x);
+            },
+            PlotMsg::PushTrace => if let Some((x,y)) = self.trace.and_then(|(i,x)| trace_point(&self.series[i],x)) {
+                ctx.props().bus.publish(PushPoint(x,y));
+                Route::Calc.navigate();
+                return false;
+            }
+        }
+        ctx.props().visible
+    }
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        if !ctx.props().visible {
+            return;
+        }
+        let canvas = match self.canvas.cast::<HtmlCanvasElement>() {
+            Some(canvas) => canvas,
+            None => return
+        };
+        // The drawing buffer follows the size the canvas is laid out at.
+        let (w,h) = (canvas.client_width().max(1),canvas.client_height().max(1));
+        if (w as f64,h as f64) != self.size {
+            canvas.set_width(w as u32);
+            canvas.set_height(h as u32);
+            self.size = (w as f64,h as f64);
+        }
+        let context = canvas.get_context("2d").ok().flatten().and_then(|c| c.dyn_into::<CanvasRenderingContext2d>().ok());
+        if let Some(cx) = context {
+            self.draw(&cx);
+        }
+    }
+}
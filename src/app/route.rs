@@ -0,0 +1,54 @@
+use crate::utils::Ignore;
+
+/// A screen addressable through the URL hash, e.g. `#/log`.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Route {
+    Calc,
+    Log,
+    Settings,
+    Help,
+    Plot
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Route::Calc
+    }
+}
+
+impl Route {
+    pub fn hash(self) -> &'static str {
+        match self {
+            Route::Calc     => "#/calc",
+            Route::Log      => "#/log",
+            Route::Settings => "#/settings",
+            Route::Help     => "#/help",
+            Route::Plot     => "#/plot"
+        }
+    }
+    pub fn from_hash(hash: &str) -> Option<Self> {
+        match hash.trim_end_matches('/') {
+            "#/calc"     => Some(Route::Calc),
+            "#/log"      => Some(Route::Log),
+            "#/settings" => Some(Route::Settings),
+            "#/help"     => Some(Route::Help),
+            "#/plot"     => Some(Route::Plot),
+            _ => None
+        }
+    }
+    /// The route named by the current location, falling back to the calculator
+    /// for an empty or unknown hash.
+    pub fn current() -> Self {
+        web_sys::window()
+            .and_then(|w| w.location().hash().ok())
+            .and_then(|h| Route::from_hash(&h))
+            .unwrap_or_default()
+    }
+    /// Pushes a history entry for this route. The resulting `hashchange` is
+    /// what actually switches screens, so back/forward take the same path.
+    pub fn navigate(self) {
+        if let Some(w) = web_sys::window() {
+            w.location().set_hash(self.hash()).ignore();
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use yew::prelude::*;
+use yew::context::ContextHandle;
+use stylist::css;
+use std::rc::Rc;
+use crate::utils::bus::Bus;
+use super::{SharedConfig,ColorTheme,EntryMode,StackModel,Backend,Rounding,Overflow,FractionStyle,ComplexStyle,Base,Route,SetConfig,Layout};
+use super::calculator::layout::Preset;
+#[allow(unused_imports)]
+use log::{trace, debug, info, warn, error};
+pub enum SettingsMsg {
+    ConfigChanged(SharedConfig),
+    Update(Box<dyn FnOnce(&mut SharedConfig)>),
+    EditLayout(String),
+    ApplyLayout
+}
+
+/// `Backend` without its settings, for picking one.
+#[derive(Clone,Copy,PartialEq)]
+enum BackendKind {
+    Float,
+    Decimal,
+    Rational,
+    Integer
+}
+
+impl BackendKind {
+    fn of(backend: Backend) -> Self {
+        match backend {
+            Backend::Float => BackendKind::Float,
+            Backend::Decimal(_) => BackendKind::Decimal,
+            Backend::Rational => BackendKind::Rational,
+            Backend::Integer(_) => BackendKind::Integer
+        }
+    }
+}
+
+pub struct Settings {
+    config: SharedConfig,
+    layout_draft: String,
+    layout_error: Option<String>,
+    _config_handle: ContextHandle<SharedConfig>
+}
+
+#[derive(PartialEq,Properties)]
+pub struct SettingsProps {
+    pub bus: Bus,
+    pub visible: bool
+}
+
+impl Settings {
+    /// Pushes edited decimal settings into the backend, if decimals are in use.
+    fn sync_decimal(c: &mut SharedConfig) {
+        if let Backend::Decimal(_) = c.backend {
+            c.backend = Backend::Decimal(c.decimal);
+        }
+    }
+    /// Pushes edited word settings into the backend, if integers are in use.
+    fn sync_integer(c: &mut SharedConfig) {
+        if let Backend::Integer(_) = c.backend {
+            c.backend = Backend::Integer(c.integer);
+        }
+    }
+    /// A row of buttons picking one of `options`, with the current one disabled.
+    fn choice<T: Copy + PartialEq + 'static>(ctx: &Context<Self>, current: T, options: &[(T,&'static str)], set: fn(&mut SharedConfig,T)) -> Html {
+        options.iter().map(|&(value,label)| html!{
+            <button disabled={ value == current } onclick={ ctx.link().callback(move |_| SettingsMsg::Update(Box::new(move |c| set(c,value)))) }> { label } </button>
+        }).collect()
+    }
+}
+
+impl Component for Settings {
+    type Message = SettingsMsg;
+    type Properties = SettingsProps;
+    fn create(ctx: &Context<Self>) -> Self {
+        let (config, _config_handle) = ctx.link()
+            .context::<SharedConfig>(ctx.link().callback(SettingsMsg::ConfigChanged))
+            .expect("Settings must be rendered inside a SharedConfig provider");
+        let layout_draft = config.keypad_layout.to_string();
+        Settings { config, layout_draft, layout_error: None, _config_handle }
+    }
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        if ctx.props().visible {
+            let presets = Preset::ALL.iter().map(|&preset| html!{
+                <button onclick={ ctx.link().callback(move |_| SettingsMsg::Update(Box::new(move |c| c.keypad_layout = Rc::new(preset.layout())))) }> { preset.name() } </button>
+            }).collect::<Html>();
+            let layout_error = match &self.layout_error {
+                Some(e) => html!{ <p> { e.as_str() } </p> },
+                None => html!{}
+            };
+            html!{
+                <div class={{ css!{
+                    display: grid;
+                    grid:   "a b b b b" 1fr
+                            ". b b b b" 15fr;
+                }}}>
+                    <button class={{css!{grid-area: a;}}} onclick={ |_| Route::Calc.navigate() }> { "Return to Calculator" } </button> <br/>
+                    <div class={{css!{grid-area: b; overflow-y: scroll;}}}>
+                        <p> { "Configuration" } </p>
+                        <button onclick={ |_| Route::Help.navigate() }> { "Help" } </button>
+                        <p> { "Color Theme" } </p>
+                        { Self::choice(ctx,self.config.color_theme,&[
+                            (ColorTheme::Light,"Light Mode"),
+                            (ColorTheme::Dark,"Dark Mode")
+                        ],|c,v| c.color_theme = v) }
+                        <p> { "Number Entry" } </p>
+                        { Self::choice(ctx,self.config.entry_mode,&[
+                            (EntryMode::AutoEnter,"Auto Enter"),
+                            (EntryMode::StackLift,"HP Stack Lift"),
+                            (EntryMode::Manual,"Manual")
+                        ],|c,v| c.entry_mode = v) }
+                        <p> { "Stack" } </p>
+                        { Self::choice(ctx,self.config.stack_model,&[
+                            (StackModel::Unbounded,"Unbounded"),
+                            (StackModel::Fixed(4),"Classic X/Y/Z/T"),
+                            (StackModel::Fixed(8),"Fixed, 8 levels")
+                        ],|c,v| c.stack_model = v) }
+                        <p> { "Numbers" } </p>
+                        { Self::choice(ctx,BackendKind::of(self.config.backend),&[
+                            (BackendKind::Float,"Binary Floating Point"),
+                            (BackendKind::Decimal,"Decimal"),
+                            (BackendKind::Rational,"Exact Fractions"),
+                            (BackendKind::Integer,"Programmer Integers")
+                        ],|c,v| c.backend = match v {
+                            BackendKind::Float => Backend::Float,
+                            BackendKind::Decimal => Backend::Decimal(c.decimal),
+                            BackendKind::Rational => Backend::Rational,
+                            BackendKind::Integer => Backend::Integer(c.integer)
+                        }) }
+                        <p> { "Integer Base" } </p>
+                        { Self::choice(ctx,self.config.number_format.base,&[
+                            (Base::Hex,"Hexadecimal"),
+                            (Base::Dec,"Decimal"),
+                            (Base::Oct,"Octal"),
+                            (Base::Bin,"Binary")
+                        ],|c,v| c.number_format.base = v) }
+                        <p> { "Fractions" } </p>
+                        { Self::choice(ctx,self.config.number_format.fractions,&[
+                            (FractionStyle::Improper,"Improper (4/3)"),
+                            (FractionStyle::Mixed,"Mixed (1 1/3)")
+                        ],|c,v| c.number_format.fractions = v) }
+                        <p> { "Complex Numbers" } </p>
+                        { Self::choice(ctx,self.config.number_format.complex,&[
+                            (ComplexStyle::Rectangular,"Rectangular (3+i4)"),
+                            (ComplexStyle::Polar,"Polar (5∠0.93)")
+                        ],|c,v| c.number_format.complex = v) }
+                        <details>
+                            <summary> { "Decimal Precision and Rounding" } </summary>
+                            { Self::choice(ctx,self.config.decimal.precision,&[
+                                (16,"16 digits"),
+                                (34,"34 digits"),
+                                (50,"50 digits"),
+                                (100,"100 digits")
+                            ],|c,v| { c.decimal.precision = v; Self::sync_decimal(c) }) }
+                            <br/>
+                            { Self::choice(ctx,self.config.decimal.rounding,&[
+                                (Rounding::HalfEven,"Half Even"),
+                                (Rounding::HalfUp,"Half Up"),
+                                (Rounding::HalfDown,"Half Down"),
+                                (Rounding::Up,"Up"),
+                                (Rounding::Down,"Down"),
+                                (Rounding::Ceiling,"Ceiling"),
+                                (Rounding::Floor,"Floor")
+                            ],|c,v| { c.decimal.rounding = v; Self::sync_decimal(c) }) }
+                        </details>
+                        <details>
+                            <summary> { "Programmer Word Size and Overflow" } </summary>
+                            { Self::choice(ctx,self.config.integer.bits,&[
+                                (8,"8 bits"),
+                                (16,"16 bits"),
+                                (32,"32 bits"),
+                                (64,"64 bits")
+                            ],|c,v| { c.integer.bits = v; Self::sync_integer(c) }) }
+                            <br/>
+                            { Self::choice(ctx,self.config.integer.signed,&[
+                                (true,"Signed"),
+                                (false,"Unsigned")
+                            ],|c,v| { c.integer.signed = v; Self::sync_integer(c) }) }
+                            <br/>
+                            { Self::choice(ctx,self.config.integer.overflow,&[
+                                (Overflow::Wrap,"Wrap Around"),
+                                (Overflow::Saturate,"Saturate"),
+                                (Overflow::Error,"Report an Error")
+                            ],|c,v| { c.integer.overflow = v; Self::sync_integer(c) }) }
+                        </details>
+                        <details>
+                            <summary> { "Keypad Layout" } </summary>
+                            <div> { presets } </div>
+                            <textarea rows="12" class={{css!{width: 100%; font-family: monospace;}}}
+                                value={ self.layout_draft.clone() }
+                                oninput={ ctx.link().callback(|e: InputEvent| SettingsMsg::EditLayout(e.target_unchecked_into::<web_sys::HtmlTextAreaElement>().value())) }
+                            ></textarea>
+                            <button onclick={ ctx.link().callback(|_| SettingsMsg::ApplyLayout) }> { "Apply Layout" } </button>
+                            { layout_error }
+                        </details>
+                    </div>
+                </div>
+            }
+        } else { html!{} }
+    }
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            SettingsMsg::ConfigChanged(config) => {
+                let changed = self.config != config;
+                if self.config.keypad_layout != config.keypad_layout {
+                    self.layout_draft = config.keypad_layout.to_string();
+                    self.layout_error = None;
+                }
+                self.config = config;
+                changed && ctx.props().visible
+            },
+            SettingsMsg::Update(f) => {
+                let mut config = self.config.clone();
+                f(&mut config);
+                ctx.props().bus.publish(SetConfig(config));
+                false
+            },
+            SettingsMsg::EditLayout(draft) => {
+                self.layout_draft = draft;
+                false
+            },
+            SettingsMsg::ApplyLayout => {
+                match self.layout_draft.parse::<Layout>() {
+                    Ok(layout) => {
+                        self.layout_error = None;
+                        let mut config = self.config.clone();
+                        config.keypad_layout = Rc::new(layout);
+                        ctx.props().bus.publish(SetConfig(config));
+                    },
+                    Err(e) => self.layout_error = Some(e.to_string())
+                }
+                true
+            }
+        }
+    }
+}
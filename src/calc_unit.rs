@@ -1,16 +1,434 @@
+use ops::{CommOrOp,Command,Native,TvmOp,Recall,Arity};
+use formula::{Formula,Step};
+pub use value::{Value,Format,FractionStyle,ComplexStyle,Base};
+pub use backend::{Backend,DecimalContext,Rounding,IntContext,Overflow};
+use std::fmt;
+
+pub mod ops;
+pub mod units;
+pub mod linalg;
+pub mod stats;
+pub mod tvm;
+pub mod formula;
+mod number_theory;
+mod value;
+mod backend;
+
+/// Shape of the stack.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum StackModel {
+    /// Grows and shrinks freely; ops on too few elements fail.
+    Unbounded,
+    /// Always holds exactly this many registers. Pushing past the top loses
+    /// the oldest value and consuming values replicates the oldest one, like
+    /// the X/Y/Z/T stack of classic HP calculators.
+    Fixed(usize)
+}
+
+impl Default for StackModel {
+    fn default() -> Self {
+        StackModel::Unbounded
+    }
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum CalcError {
+    StackUnderflow { needed: usize, available: usize },
+    DivisionByZero,
+    InvalidNumber(String),
+    NothingToRecall,
+    NotAnInteger(String),
+    Overflow,
+    IncompatibleUnits,
+    ShapeMismatch,
+    SingularMatrix,
+    NotEnoughData,
+    NoSuchPoint,
+    Unordered,
+    NothingToUndo,
+    Negative(String),
+    OutOfDomain,
+    TooLarge,
+    NoSolution,
+    NoConvergence,
+    NoFormula,
+    /// An error reported by rpncalc.
+    Engine(String)
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::StackUnderflow { needed, available } => write!(f,"Needs {needed} stack elements, but only {available} available"),
+            CalcError::DivisionByZero => write!(f,"Division by zero"),
+            CalcError::InvalidNumber(s) => write!(f,"'{s}' is not a valid number"),
+            CalcError::NothingToRecall => write!(f,"No arguments to recall"),
+            CalcError::NotAnInteger(s) => write!(f,"'{s}' is not an integer"),
+            CalcError::Overflow => write!(f,"Result does not fit the word size"),
+            CalcError::IncompatibleUnits => write!(f,"Units are not compatible"),
+            CalcError::ShapeMismatch => write!(f,"Operands have incompatible shapes"),
+            CalcError::SingularMatrix => write!(f,"Matrix is singular"),
+            CalcError::NotEnoughData => write!(f,"Not enough statistics data"),
+            CalcError::NoSuchPoint => write!(f,"No such data point"),
+            CalcError::Unordered => write!(f,"Values have no order"),
+            CalcError::NothingToUndo => write!(f,"Nothing to undo"),
+            CalcError::Negative(s) => write!(f,"'{s}' must not be negative"),
+            CalcError::OutOfDomain => write!(f,"Argument is outside the function's domain"),
+            CalcError::TooLarge => write!(f,"Number is too large"),
+            CalcError::NoSolution => write!(f,"No solution found"),
+            CalcError::NoConvergence => write!(f,"Did not converge"),
+            CalcError::NoFormula => write!(f,"No formula recorded"),
+            CalcError::Engine(s) => write!(f,"{s}")
+        }
+    }
+}
+
+pub type CalcResult<T> = Result<T,CalcError>;
+
+/// How many steps back `CalcUnit::undo` can go.
+const UNDO_DEPTH: usize = 100;
+
+/// The state a step may change, as `CalcUnit::undo` puts it back.
+#[derive(Clone,PartialEq)]
+struct Snapshot {
+    stack: Vec<Value>,
+    last_args: Vec<Value>,
+    stats: stats::Stats,
+    tvm: tvm::Tvm
+}
+
 #[derive(Default)]
 pub struct CalcUnit {
-    stack: Vec<f64>
+    stack: Vec<Value>,
+    model: StackModel,
+    backend: Backend,
+    /// Arguments consumed by the last op, X last.
+    last_args: Vec<Value>,
+    stats: stats::Stats,
+    tvm: tvm::Tvm,
+    formula: Formula,
+    /// Steps recorded so far while a formula is being recorded.
+    recording: Option<Vec<Step>>,
+    /// The state before each recent step, latest last, with how many steps
+    /// had been recorded by then.
+    history: Vec<(Snapshot,usize)>
 }
 
 impl CalcUnit {
-    pub fn run_command(&mut self, comm: rpncalc::CommandOrOp) -> (Vec<u8>,rpncalc::error::Result<Option<String>>) {
-        use rpncalc::Command;
-        let mut stdout = std::io::Cursor::new(vec![]);
-        let out = comm.comm(&mut self.stack, std::io::empty(),&mut stdout);
-        (stdout.into_inner(),out)
+    /// Reads `text` with the current backend.
+    pub fn parse(&self, text: &str) -> CalcResult<Value> {
+        self.backend.parse(text)
+    }
+    /// Parses `text` with the current backend and pushes it.
+    pub fn push_str(&mut self, text: &str) -> CalcResult<()> {
+        let v = self.parse(text)?;
+        self.push(v);
+        Ok(())
+    }
+    pub fn push(&mut self, v: Value) {
+        self.record(Step::Push(v.clone()));
+        self.stack.push(v);
+        self.normalize();
+    }
+    /// Runs `op` without updating LASTX/LASTARG, for stack housekeeping done
+    /// on the user's behalf.
+    pub fn apply(&mut self, op: CommOrOp) -> (Vec<u8>,CalcResult<Option<String>>) {
+        let mut stdout = vec![];
+        let res = self.eval(op,None,&mut stdout);
+        self.normalize();
+        if res.is_ok() {
+            self.record(Step::Run(op,None));
+        }
+        (stdout,res)
+    }
+    /// Runs `op`, remembering the arguments it consumed for LASTX/LASTARG.
+    pub fn run_op(&mut self, op: CommOrOp) -> (Vec<u8>,CalcResult<Option<String>>) {
+        self.run_op_top(op,None)
+    }
+    /// Like `run_op`, but an op on the whole stack only takes its `top` levels.
+    pub fn run_op_top(&mut self, op: CommOrOp, top: Option<usize>) -> (Vec<u8>,CalcResult<Option<String>>) {
+        let args = match op.arity() {
+            Arity::Fixed(n) if n > 0 && n <= self.stack.len() => Some(self.stack[self.stack.len() - n..].to_vec()),
+            _ => None
+        };
+        let mut stdout = vec![];
+        let res = self.eval(op,top,&mut stdout);
+        self.normalize();
+        if let (Some(args),Ok(_)) = (args,&res) {
+            self.last_args = args;
+        }
+        if res.is_ok() {
+            self.record(Step::Run(op,top));
+        }
+        (stdout,res)
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            stack: self.stack.clone(),
+            last_args: self.last_args.clone(),
+            stats: self.stats.clone(),
+            tvm: self.tvm.clone()
+        }
+    }
+    /// Remembers the stack, LASTARG, statistics and TVM registers as they
+    /// are, so the step about to be taken can be undone in one go.
+    pub fn checkpoint(&mut self) {
+        let now = self.snapshot();
+        if self.history.last().map(|(then,_)| then) == Some(&now) {
+            return;
+        }
+        if self.history.len() == UNDO_DEPTH {
+            self.history.remove(0);
+        }
+        let recorded = self.recording.as_ref().map_or(0,Vec::len);
+        self.history.push((now,recorded));
+    }
+    /// Puts back the state from before the latest step that changed it.
+    pub fn undo(&mut self) -> CalcResult<()> {
+        while let Some((then,recorded)) = self.history.pop() {
+            if then != self.snapshot() {
+                self.stack = then.stack;
+                self.last_args = then.last_args;
+                self.stats = then.stats;
+                self.tvm = then.tvm;
+                if let Some(steps) = &mut self.recording {
+                    steps.truncate(recorded);
+                }
+                self.normalize();
+                return Ok(());
+            }
+        }
+        Err(CalcError::NothingToUndo)
+    }
+    fn eval(&mut self, op: CommOrOp, top: Option<usize>, stdout: &mut Vec<u8>) -> CalcResult<Option<String>> {
+        let whole = top.unwrap_or(self.stack.len());
+        match op {
+            CommOrOp::Recall(r) => {
+                let args = match r {
+                    Recall::LastX => self.last_args.last().map(std::slice::from_ref).unwrap_or(&[]),
+                    Recall::LastArgs => &self.last_args[..]
+                };
+                if args.is_empty() {
+                    return Err(CalcError::NothingToRecall);
+                }
+                self.stack.extend_from_slice(args);
+            },
+            CommOrOp::Command(c) => match c {
+                Command::Drop => { self.take(1)?; },
+                Command::Dup => {
+                    let x = self.take(1)?;
+                    self.stack.extend(x.clone());
+                    self.stack.extend(x);
+                },
+                Command::Swap => {
+                    let mut yx = self.take(2)?;
+                    yx.swap(0,1);
+                    self.stack.extend(yx);
+                },
+                Command::Rev => {
+                    let mut top = self.take(whole)?;
+                    top.reverse();
+                    self.stack.extend(top);
+                }
+            },
+            CommOrOp::Native(native @ (Native::Stats(_) | Native::Tvm(_) | Native::Formula(_))) => {
+                let n = match op.arity() {
+                    Arity::Fixed(n) => n,
+                    Arity::Whole => whole
+                };
+                let args = self.take(n)?;
+                let res = args.iter()
+                    .map(|v| match v.to_f64() {
+                        x if x.is_nan() => Err(CalcError::InvalidNumber(v.to_string())),
+                        x => Ok(x)
+                    }).collect::<CalcResult<Vec<_>>>()
+                    .and_then(|xs| match native {
+                        Native::Stats(s) => self.stats.eval(s,&xs),
+                        Native::Tvm(t) => self.tvm.eval(t,&xs),
+                        Native::Formula(f) => self.formula.eval_op(f,&xs),
+                        _ => unreachable!()
+                    });
+                match res {
+                    // Storing leaves X exactly as it was.
+                    Ok(_) if matches!(native,Native::Tvm(TvmOp::Store(_))) => self.stack.extend(args),
+                    Ok(results) => self.stack.extend(results.into_iter().map(Value::Float)),
+                    Err(e) => {
+                        self.stack.extend(args);
+                        return Err(e);
+                    }
+                }
+            },
+            CommOrOp::Op(_) | CommOrOp::Native(_) => {
+                let n = match op.arity() {
+                    Arity::Fixed(n) => n,
+                    Arity::Whole => whole
+                };
+                let args = self.take(n)?;
+                let res = match op {
+                    CommOrOp::Native(native) => self.backend.eval_native(native,args.clone(),stdout),
+                    CommOrOp::Op(o) => self.backend.eval(o,args.clone(),stdout),
+                    _ => unreachable!()
+                };
+                match res {
+                    Ok(results) => self.stack.extend(results),
+                    Err(e) => {
+                        self.stack.extend(args);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+    /// Pops the top `n` values, X last.
+    fn take(&mut self, n: usize) -> CalcResult<Vec<Value>> {
+        let available = self.stack.len();
+        if available < n {
+            return Err(CalcError::StackUnderflow { needed: n, available });
+        }
+        Ok(self.stack.split_off(available - n))
     }
-    pub fn get_stack(&self) -> &[f64] {
+    pub fn get_stack(&self) -> &[Value] {
         &self.stack
     }
-}
\ No newline at end of file
+    pub fn stat_points(&self) -> &[stats::Point] {
+        &self.stats.points
+    }
+    pub fn tvm(&self) -> &tvm::Tvm {
+        &self.tvm
+    }
+    pub fn formula(&self) -> &Formula {
+        &self.formula
+    }
+    pub fn recording(&self) -> bool {
+        self.recording.is_some()
+    }
+    /// Starts recording the steps taken from here on as a formula of the
+    /// value in X.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(vec![]);
+    }
+    /// Stops recording and makes what was recorded the formula.
+    pub fn stop_recording(&mut self) -> &Formula {
+        if let Some(steps) = self.recording.take() {
+            self.formula = Formula { steps, backend: self.backend };
+        }
+        &self.formula
+    }
+    fn record(&mut self, step: Step) {
+        if let Some(steps) = &mut self.recording {
+            steps.push(step);
+        }
+    }
+    /// Corrects or, given `None`, deletes an accumulated point.
+    pub fn edit_stat_point(&mut self, i: usize, point: Option<stats::Point>) -> CalcResult<()> {
+        if i >= self.stats.points.len() {
+            return Err(CalcError::NoSuchPoint);
+        }
+        match point {
+            Some(p) => self.stats.points[i] = p,
+            None => { self.stats.points.remove(i); }
+        }
+        Ok(())
+    }
+    pub fn model(&self) -> StackModel {
+        self.model
+    }
+    pub fn set_model(&mut self, model: StackModel) {
+        self.model = model;
+        self.normalize();
+    }
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+    /// Brings a fixed-depth stack back to its depth, dropping the oldest
+    /// values or replicating the oldest one (zero when empty).
+    fn normalize(&mut self) {
+        if let StackModel::Fixed(depth) = self.model {
+            if self.stack.len() > depth {
+                self.stack.drain(..self.stack.len() - depth);
+            }
+            while self.stack.len() < depth {
+                let oldest = self.stack.first().cloned().unwrap_or_else(|| self.backend.zero());
+                self.stack.insert(0,oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ops::StatOp;
+
+    fn words() -> CalcUnit {
+        let mut unit = CalcUnit::default();
+        unit.set_backend(Backend::Integer(IntContext::default()));
+        unit
+    }
+
+    fn run(unit: &mut CalcUnit, op: Native) {
+        unit.run_op(CommOrOp::Native(op)).1.unwrap();
+    }
+
+    #[test]
+    fn statistics_are_not_truncated_in_words() {
+        let mut unit = words();
+        for x in [1,2] {
+            unit.push(Value::Integer(x));
+            run(&mut unit,Native::Stats(StatOp::Add));
+        }
+        run(&mut unit,Native::Stats(StatOp::Mean));
+        assert_eq!(unit.get_stack().last(),Some(&Value::Float(1.5)));
+        run(&mut unit,Native::Stats(StatOp::SDev));
+        assert_eq!(unit.get_stack().last(),Some(&Value::Float(0.5f64.sqrt())));
+    }
+    #[test]
+    fn undo_puts_back_statistics_and_registers() {
+        let mut unit = CalcUnit::default();
+        unit.push(Value::Float(4.0));
+        unit.checkpoint();
+        run(&mut unit,Native::Stats(StatOp::Add));
+        assert_eq!(unit.stat_points().len(),1);
+        unit.checkpoint();
+        run(&mut unit,Native::Tvm(TvmOp::Store(ops::TvmVar::Pv)));
+        assert_eq!(unit.tvm().pv,1.0);
+        unit.undo().unwrap();
+        assert_eq!(unit.tvm().pv,0.0);
+        assert_eq!(unit.stat_points().len(),1);
+        unit.undo().unwrap();
+        assert!(unit.stat_points().is_empty());
+        assert_eq!(unit.get_stack(),&[Value::Float(4.0)]);
+        assert_eq!(unit.undo(),Err(CalcError::NothingToUndo));
+    }
+
+    #[test]
+    fn undo_puts_back_the_last_arguments() {
+        let mut unit = CalcUnit::default();
+        unit.push(Value::Float(1.0));
+        unit.push(Value::Float(2.0));
+        unit.checkpoint();
+        unit.run_op(CommOrOp::Op(ops::Op::Arith(ops::Arith::Add))).1.unwrap();
+        unit.checkpoint();
+        unit.push(Value::Float(5.0));
+        unit.run_op(CommOrOp::Op(ops::Op::Exp(ops::Exp::Root2))).1.unwrap();
+        unit.undo().unwrap();
+        unit.run_op(CommOrOp::Recall(Recall::LastArgs)).1.unwrap();
+        assert_eq!(unit.get_stack(),&[Value::Float(3.0),Value::Float(1.0),Value::Float(2.0)]);
+    }
+    #[test]
+    fn edited_points_are_their_own_undo_step() {
+        let mut unit = CalcUnit::default();
+        for x in [1.0,2.0] {
+            unit.checkpoint();
+            unit.push(Value::Float(x));
+            run(&mut unit,Native::Stats(StatOp::Add));
+        }
+        unit.checkpoint();
+        unit.edit_stat_point(0,Some(stats::Point { x: 5.0, y: None })).unwrap();
+        assert_eq!(unit.edit_stat_point(2,None),Err(CalcError::NoSuchPoint));
+        unit.undo().unwrap();
+        assert_eq!(unit.stat_points().len(),2);
+        assert_eq!(unit.stat_points()[0].x,1.0);
+    }
+}
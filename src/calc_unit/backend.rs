@@ -0,0 +1,895 @@
+use super::value::{Value,decimal_to_rational,check_scale,parse_complex};
+use super::units;
+use super::linalg::{self,Matrix};
+use super::number_theory;
+use super::ops::{Op,Arith,Constant,Exp2,Exp,Trig,Native,Convert,ComplexOp,Hyperbolic,Power,Part,NumberOp,PercentOp,Bitwise,WholeOp,ExtractCommand};
+use super::CalcError;
+use bigdecimal::{BigDecimal,RoundingMode};
+use num_bigint::{BigInt,BigUint};
+use num_rational::BigRational;
+use num_complex::Complex64;
+use num_traits::{Zero,One,Signed,ToPrimitive};
+use std::cmp::Ordering;
+use std::num::NonZeroU64;
+use std::str::FromStr;
+
+const PI: &str = "3.14159265358979323846264338327950288419716939937510582097494459230781640628620899862803482534211706798214808651";
+const E: &str  = "2.71828182845904523536028747135266249775724709369995957496696762772407663035354759457138217852516642742746639193";
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Rounding {
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    Up,
+    Down,
+    Ceiling,
+    Floor
+}
+
+impl From<Rounding> for RoundingMode {
+    fn from(r: Rounding) -> Self {
+        match r {
+            Rounding::HalfEven => RoundingMode::HalfEven,
+            Rounding::HalfUp   => RoundingMode::HalfUp,
+            Rounding::HalfDown => RoundingMode::HalfDown,
+            Rounding::Up       => RoundingMode::Up,
+            Rounding::Down     => RoundingMode::Down,
+            Rounding::Ceiling  => RoundingMode::Ceiling,
+            Rounding::Floor    => RoundingMode::Floor
+        }
+    }
+}
+
+/// Significant digits kept by the decimal backend, and how to drop the rest.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct DecimalContext {
+    pub precision: u64,
+    pub rounding: Rounding
+}
+
+impl Default for DecimalContext {
+    fn default() -> Self {
+        DecimalContext { precision: 34, rounding: Rounding::HalfEven }
+    }
+}
+
+impl DecimalContext {
+    pub fn round(&self, d: &BigDecimal) -> BigDecimal {
+        d.with_precision_round(NonZeroU64::new(self.precision.max(1)).unwrap(),self.rounding.into())
+    }
+    /// Exact integer power by repeated squaring, with a few guard digits.
+    fn powi(&self, base: &BigDecimal, exp: i64) -> Result<BigDecimal,CalcError> {
+        if exp < 0 && base.is_zero() {
+            return Err(CalcError::DivisionByZero);
+        }
+        let work = DecimalContext { precision: self.precision + 8, ..*self };
+        let mut result = BigDecimal::one();
+        let mut square = base.clone();
+        let mut e = exp.unsigned_abs();
+        while e > 0 {
+            if e & 1 == 1 {
+                result = work.round(&(&result * &square));
+            }
+            square = work.round(&(&square * &square));
+            e >>= 1;
+        }
+        if exp < 0 {
+            result = BigDecimal::one() / result;
+        }
+        Ok(self.round(&result))
+    }
+}
+
+/// What integer ops do with results outside the word.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Overflow {
+    /// Keep the low bits, like the hardware does.
+    Wrap,
+    /// Clamp to the smallest or largest word.
+    Saturate,
+    /// Refuse the op and leave the stack alone.
+    Error
+}
+
+/// Word size, signedness and overflow handling of the integer backend.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct IntContext {
+    /// 8, 16, 32 or 64.
+    pub bits: u32,
+    pub signed: bool,
+    pub overflow: Overflow
+}
+
+impl Default for IntContext {
+    fn default() -> Self {
+        IntContext { bits: 64, signed: true, overflow: Overflow::Wrap }
+    }
+}
+
+impl IntContext {
+    pub fn min(&self) -> i128 {
+        if self.signed { -(1 << (self.bits - 1)) } else { 0 }
+    }
+    pub fn max(&self) -> i128 {
+        if self.signed { (1 << (self.bits - 1)) - 1 } else { (1 << self.bits) - 1 }
+    }
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - self.bits)
+    }
+    /// The word's bit pattern.
+    fn to_bits(&self, v: i128) -> u64 {
+        (v as u64) & self.mask()
+    }
+    /// Reads a bit pattern, sign-extending for signed words.
+    fn from_bits(&self, bits: u64) -> i128 {
+        let bits = bits & self.mask();
+        if self.signed && (bits >> (self.bits - 1)) & 1 == 1 {
+            bits as i128 - (1 << self.bits)
+        } else {
+            bits as i128
+        }
+    }
+    /// Brings `v` into the word's range as the overflow setting dictates.
+    pub fn fit(&self, v: i128) -> Result<i128,CalcError> {
+        if (self.min()..=self.max()).contains(&v) {
+            return Ok(v);
+        }
+        match self.overflow {
+            Overflow::Wrap => Ok(self.from_bits(v as u64)),
+            Overflow::Saturate => Ok(v.clamp(self.min(),self.max())),
+            Overflow::Error => Err(CalcError::Overflow)
+        }
+    }
+    /// Like `fit`, for results too large even for `i128`; `wrapped` is the
+    /// result modulo 2^128 and `negative` its true sign.
+    fn overflowed(&self, wrapped: i128, negative: bool) -> Result<i128,CalcError> {
+        match self.overflow {
+            Overflow::Wrap => Ok(self.from_bits(wrapped as u64)),
+            Overflow::Saturate => Ok(if negative { self.min() } else { self.max() }),
+            Overflow::Error => Err(CalcError::Overflow)
+        }
+    }
+    fn arith(&self, a: Arith, y: i128, x: i128) -> Result<i128,CalcError> {
+        let exact = match a {
+            Arith::Add => y.checked_add(x),
+            Arith::Sub => y.checked_sub(x),
+            Arith::Mul => y.checked_mul(x),
+            Arith::Div => {
+                if x == 0 {
+                    return Err(CalcError::DivisionByZero);
+                }
+                y.checked_div(x)
+            }
+        };
+        match exact {
+            Some(v) => self.fit(v),
+            // Words are at most 64 bits, so only a product can get here.
+            None => self.overflowed(y.wrapping_mul(x),(y < 0) != (x < 0))
+        }
+    }
+    fn pow(&self, y: i128, x: u32) -> Result<i128,CalcError> {
+        match y.checked_pow(x) {
+            Some(v) => self.fit(v),
+            None => self.overflowed(y.wrapping_pow(x),y < 0 && x % 2 == 1)
+        }
+    }
+    fn bitwise(&self, op: Bitwise, args: &[i128]) -> Result<i128,CalcError> {
+        let count = |x: i128| u32::try_from(x).map_err(|_| CalcError::InvalidNumber(x.to_string()));
+        let bits = self.bits;
+        let result = match (op,args) {
+            (Bitwise::Not,&[x]) => !self.to_bits(x),
+            (Bitwise::And,&[y,x]) => self.to_bits(y) & self.to_bits(x),
+            (Bitwise::Or ,&[y,x]) => self.to_bits(y) | self.to_bits(x),
+            (Bitwise::Xor,&[y,x]) => self.to_bits(y) ^ self.to_bits(x),
+            (Bitwise::Shl,&[y,x]) => self.to_bits(y).checked_shl(count(x)?).unwrap_or(0),
+            (Bitwise::Shr,&[y,x]) => {
+                let n = count(x)?.min(bits);
+                if self.signed {
+                    return Ok(self.from_bits(((self.from_bits(self.to_bits(y)) >> n) as u64) & self.mask()));
+                }
+                self.to_bits(y).checked_shr(n).unwrap_or(0)
+            },
+            (Bitwise::RotL,&[y,x]) | (Bitwise::RotR,&[y,x]) => {
+                let n = x.rem_euclid(bits as i128) as u32;
+                let n = if op == Bitwise::RotL { n } else { (bits - n) % bits };
+                let y = self.to_bits(y);
+                if n == 0 { y } else { (y << n) | (y >> (bits - n)) }
+            },
+            _ => unreachable!("arity checked by CalcUnit")
+        };
+        Ok(self.from_bits(result))
+    }
+}
+
+/// The number type new values are created in and ops are carried out with.
+/// Values left on the stack from another backend are converted when consumed.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Backend {
+    /// IEEE doubles, computed by rpncalc.
+    Float,
+    /// Arbitrary-precision decimals. Transcendental ops have no exact
+    /// decimal form and go through `f64`.
+    Decimal(DecimalContext),
+    /// Exact fractions. Arithmetic and integer powers stay exact; anything
+    /// else gives up exactness and yields a float.
+    Rational,
+    /// Fixed-size machine words, for programmer mode. Non-integer results
+    /// are floats.
+    Integer(IntContext)
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Float
+    }
+}
+
+impl Backend {
+    pub fn parse(&self, text: &str) -> Result<Value,CalcError> {
+        let text = text.trim_end_matches('.');
+        let invalid = || CalcError::InvalidNumber(text.to_string());
+        if text.contains(|c| c == 'i' || c == '∠') {
+            return parse_complex(text).map(|c| self.demote(c)).ok_or_else(invalid);
+        }
+        match self {
+            // Integers typed in full keep every digit.
+            Backend::Float => match BigInt::from_str(text) {
+                Ok(n) => self.integer_result(n),
+                Err(_) => text.parse::<f64>().map(Value::Float).map_err(|_| invalid())
+            },
+            Backend::Decimal(ctx) => BigDecimal::from_str(text).map(|d| Value::Decimal(ctx.round(&d))).map_err(|_| invalid()),
+            Backend::Rational => {
+                let d = BigDecimal::from_str(text).map_err(|_| invalid())?;
+                Ok(Value::Rational(decimal_to_rational(&d)?))
+            },
+            Backend::Integer(ctx) => {
+                let v = BigDecimal::from_str(text).map_err(|_| invalid())?;
+                check_scale(&v)?;
+                let i = Value::Decimal(v).trunc_integer().ok_or_else(invalid)?;
+                Ok(Value::Integer(ctx.fit(i)?))
+            }
+        }
+    }
+    pub fn zero(&self) -> Value {
+        match self {
+            Backend::Float => Value::Float(0.0),
+            Backend::Decimal(_) => Value::Decimal(BigDecimal::zero()),
+            Backend::Rational => Value::Rational(BigRational::zero()),
+            Backend::Integer(_) => Value::Integer(0)
+        }
+    }
+    /// Converts a value into this backend's number type, where possible.
+    /// Floats are never turned into fractions, since they are not exact, and
+    /// complex numbers, quantities and arrays stay as they are. So do big
+    /// integers, unless fractions or words can take them. Words only take
+    /// integral values; other reals become floats rather than being cut short.
+    pub fn coerce(&self, v: Value) -> Value {
+        match self {
+            Backend::Float => match v {
+                Value::Complex(_) | Value::Quantity(..) | Value::Vector(_) | Value::Matrix(_) | Value::BigInt(_) => v,
+                _ => Value::Float(v.to_f64())
+            },
+            Backend::Decimal(_) if matches!(v,Value::BigInt(_)) => v,
+            Backend::Decimal(ctx) => match v.to_decimal() {
+                Some(d) => Value::Decimal(ctx.round(&d)),
+                None => v
+            },
+            Backend::Rational => match v.to_rational() {
+                Some(r) => Value::Rational(r),
+                None => v
+            },
+            Backend::Integer(ctx) => match v.to_integer().map(|i| ctx.fit(i)) {
+                Some(Ok(i)) => Value::Integer(i),
+                None if matches!(v,Value::Decimal(_) | Value::Rational(_)) => Value::Float(v.to_f64()),
+                _ => v
+            }
+        }
+    }
+    /// Turns a complex result without an imaginary part back into a real.
+    fn demote(&self, c: Complex64) -> Value {
+        if c.im == 0.0 {
+            self.coerce(Value::Float(c.re))
+        } else {
+            Value::Complex(c)
+        }
+    }
+    /// Runs `op` on `args` (X last) and returns the values to push back.
+    /// Arrays go through linear algebra and quantities through unit
+    /// arithmetic. Complex arguments, and real ones the op has no real answer for, are
+    /// computed in the complex plane.
+    pub fn eval(&self, op: Op, args: Vec<Value>, stdout: &mut Vec<u8>) -> Result<Vec<Value>,CalcError> {
+        if args.iter().any(|v| matches!(v,Value::Vector(_) | Value::Matrix(_))) {
+            let mut scalar_op = |op: Op, xs: &[f64]| -> Result<f64,CalcError> {
+                let xs = xs.iter().copied().map(Value::Float).collect::<Vec<_>>();
+                Ok(float_eval(op,&xs,stdout)?.pop().unwrap_or(f64::NAN))
+            };
+            return Ok(vec![linalg::eval(op,&args,&mut scalar_op)?]);
+        }
+        if args.iter().any(|v| matches!(v,Value::Quantity(..))) {
+            return Ok(vec![self.coerce(units::eval(op,&args)?)]);
+        }
+        if args.iter().any(|v| matches!(v,Value::Complex(_))) {
+            return self.complex_eval(op,&args);
+        }
+        if let Some(result) = self.big_integer_eval(op,&args)? {
+            return Ok(vec![result]);
+        }
+        let results = self.real_eval(op,args.clone(),stdout)?;
+        if results.iter().any(Value::is_nan) && !args.iter().any(Value::is_nan) {
+            if let Ok(results) = self.complex_eval(op,&args) {
+                return Ok(results);
+            }
+        }
+        Ok(results)
+    }
+    fn real_eval(&self, op: Op, args: Vec<Value>, stdout: &mut Vec<u8>) -> Result<Vec<Value>,CalcError> {
+        let args = args.into_iter().map(|v| self.coerce(v)).collect::<Vec<_>>();
+        let ctx = match self {
+            Backend::Float => return Ok(float_eval(op,&args,stdout)?.into_iter().map(Value::Float).collect()),
+            Backend::Decimal(ctx) => ctx,
+            Backend::Rational => return rational_eval(op,&args,stdout),
+            Backend::Integer(ctx) => return integer_eval(ctx,op,&args,stdout)
+        };
+        let decimals = args.iter().map(Value::to_decimal).collect::<Option<Vec<_>>>();
+        let result = match (op,decimals.as_deref()) {
+            (Op::Arith(a),Some([y,x])) => ctx.round(&match a {
+                Arith::Add => y + x,
+                Arith::Sub => y - x,
+                Arith::Mul => y * x,
+                Arith::Div => {
+                    if x.is_zero() {
+                        return Err(CalcError::DivisionByZero);
+                    }
+                    y / x
+                }
+            }),
+            (Op::Exp2(Exp2::Pow),Some([y,x])) if x.is_integer() && x.abs() <= BigDecimal::from(1_000_000) => {
+                ctx.powi(y,x.to_i64().unwrap())?
+            },
+            (Op::Exp(Exp::Root2),Some([x])) if *x >= BigDecimal::zero() => {
+                ctx.round(&x.sqrt().unwrap())
+            },
+            (Op::Constant(Constant::Pi),_) => ctx.round(&BigDecimal::from_str(PI).unwrap()),
+            (Op::Constant(Constant::E),_)  => ctx.round(&BigDecimal::from_str(E).unwrap()),
+            _ => return Ok(float_eval(op,&args,stdout)?.into_iter().map(|f| self.coerce(Value::Float(f))).collect())
+        };
+        Ok(vec![Value::Decimal(result)])
+    }
+    fn complex_eval(&self, op: Op, args: &[Value]) -> Result<Vec<Value>,CalcError> {
+        let args = args.iter().map(Value::to_complex).collect::<Vec<_>>();
+        let result = match (op,&args[..]) {
+            (Op::Arith(a),[y,x]) => match a {
+                Arith::Add => y + x,
+                Arith::Sub => y - x,
+                Arith::Mul => y * x,
+                Arith::Div => {
+                    if x.is_zero() {
+                        return Err(CalcError::DivisionByZero);
+                    }
+                    y / x
+                }
+            },
+            (Op::Exp2(e),[y,x]) => match e {
+                Exp2::Pow => y.powc(*x),
+                Exp2::LogN => y.ln() / x.ln(),
+                Exp2::RootN => y.powc(x.inv())
+            },
+            (Op::Exp(e),[x]) => match e {
+                Exp::Log10 => x.ln() / std::f64::consts::LN_10,
+                Exp::Log2 => x.ln() / std::f64::consts::LN_2,
+                Exp::LogE => x.ln(),
+                Exp::Root2 => x.sqrt()
+            },
+            (Op::Trig(t),[x]) => match t {
+                Trig::Sin => x.sin(),
+                Trig::Cos => x.cos(),
+                Trig::Tan => x.tan(),
+                Trig::ASin => x.asin(),
+                Trig::ACos => x.acos(),
+                Trig::ATan => x.atan()
+            },
+            _ => return Err(no_complex_form())
+        };
+        Ok(vec![self.demote(result)])
+    }
+    /// Runs a native op on `args` (X last), untouched by coercion.
+    pub fn eval_native(&self, op: Native, mut args: Vec<Value>, stdout: &mut Vec<u8>) -> Result<Vec<Value>,CalcError> {
+        match op {
+            Native::Convert(Convert::ToggleFraction) => {
+                let x = args.pop().unwrap();
+                let toggled = match x {
+                    Value::Rational(_) => match self {
+                        Backend::Decimal(ctx) => Value::Decimal(ctx.round(&x.to_decimal().unwrap())),
+                        _ => Value::Float(x.to_f64())
+                    },
+                    _ => match x.approximate_rational() {
+                        Some(r) => Value::Rational(r),
+                        None => return Err(CalcError::InvalidNumber(x.to_string()))
+                    }
+                };
+                Ok(vec![toggled])
+            },
+            Native::Complex(c) => {
+                let x = args.pop().unwrap();
+                let result = match (c,x) {
+                    (ComplexOp::Conj,Value::Complex(z)) => Value::Complex(z.conj()),
+                    (ComplexOp::Re,Value::Complex(z)) => self.coerce(Value::Float(z.re)),
+                    (ComplexOp::Im,Value::Complex(z)) => self.coerce(Value::Float(z.im)),
+                    (ComplexOp::Arg,Value::Complex(z)) => self.coerce(Value::Float(z.arg())),
+                    (ComplexOp::Conj,x) | (ComplexOp::Re,x) => x,
+                    (ComplexOp::Im,_) => self.zero(),
+                    (ComplexOp::Abs,x) => self.coerce(x.abs()),
+                    (ComplexOp::Arg,x) => self.coerce(Value::Float(if x.to_f64() < 0.0 { std::f64::consts::PI } else { 0.0 }))
+                };
+                Ok(vec![result])
+            },
+            Native::Bitwise(b) => {
+                let ctx = match self {
+                    Backend::Integer(ctx) => *ctx,
+                    _ => IntContext::default()
+                };
+                let ints = args.iter()
+                    .map(|v| v.to_integer().ok_or_else(|| CalcError::NotAnInteger(v.to_string())))
+                    .collect::<Result<Vec<_>,_>>()?;
+                Ok(vec![self.coerce(Value::Integer(ctx.bitwise(b,&ints)?))])
+            },
+            Native::Hyperbolic(h) => {
+                let x = args.pop().unwrap();
+                Ok(vec![match h {
+                    Hyperbolic::Sinh  => self.map_real(x,f64::sinh ,Some(&|z: Complex64| z.sinh ())),
+                    Hyperbolic::Cosh  => self.map_real(x,f64::cosh ,Some(&|z: Complex64| z.cosh ())),
+                    Hyperbolic::Tanh  => self.map_real(x,f64::tanh ,Some(&|z: Complex64| z.tanh ())),
+                    Hyperbolic::ASinh => self.map_real(x,f64::asinh,Some(&|z: Complex64| z.asinh())),
+                    Hyperbolic::ACosh => self.map_real(x,f64::acosh,Some(&|z: Complex64| z.acosh())),
+                    Hyperbolic::ATanh => self.map_real(x,f64::atanh,Some(&|z: Complex64| z.atanh()))
+                }?])
+            },
+            Native::Power(Power::E) => Ok(vec![self.map_real(args.pop().unwrap(),f64::exp,Some(&|z: Complex64| z.exp()))?]),
+            Native::Power(p) => {
+                // Integer powers of 10 and 2 are exact in every backend that can hold them.
+                let base = self.coerce(Value::Integer(if p == Power::Ten { 10 } else { 2 }));
+                self.eval(Op::Exp2(Exp2::Pow),vec![base,args.pop().unwrap()],stdout)
+            },
+            Native::Part(p) => Ok(vec![self.eval_part(p,args)?]),
+            Native::NumberTheory(n) => {
+                // Factorials of anything but an integer come from the Γ function.
+                if let (NumberOp::Factorial | NumberOp::Gamma,[x]) = (n,&args[..]) {
+                    real(x)?;
+                    if number_theory::to_big_integer(x).is_err() {
+                        let x = if n == NumberOp::Factorial { x.to_f64() + 1.0 } else { x.to_f64() };
+                        return Ok(vec![self.coerce(Value::Float(number_theory::gamma(x)))]);
+                    }
+                }
+                let ints = args.iter()
+                    .map(|v| real(v).and_then(|_| number_theory::to_big_integer(v)))
+                    .collect::<Result<Vec<_>,_>>()?;
+                number_theory::eval(n,&ints)?.into_iter().map(|i| self.integer_result(i)).collect()
+            },
+            Native::Percent(p) => self.eval_percent(p,args,stdout),
+            Native::Units(u) => Ok(vec![self.coerce(units::eval_native(u,args)?)]),
+            Native::Linalg(l) => Ok(vec![self.coerce(linalg::eval_native(l,&args)?)]),
+            Native::Stats(_) | Native::Tvm(_) | Native::Formula(_) => unreachable!("served by CalcUnit"),
+            Native::Whole(w) => self.eval_whole(w,args,stdout)
+        }
+    }
+    /// Sums, differences, products, exact quotients and powers of integral
+    /// decimals and floats small enough to be exact, computed exactly so that results too large for
+    /// the backend's type keep every digit. `None` leaves `op` to the backend.
+    fn big_integer_eval(&self, op: Op, args: &[Value]) -> Result<Option<Value>,CalcError> {
+        if matches!(self,Backend::Rational | Backend::Integer(_)) {
+            return Ok(None);
+        }
+        let ints = match args.iter().map(integral).collect::<Option<Vec<_>>>() {
+            Some(ints) => ints,
+            None => return Ok(None)
+        };
+        let result = match (op,&ints[..]) {
+            (Op::Arith(Arith::Add),[y,x]) => y + x,
+            (Op::Arith(Arith::Sub),[y,x]) => y - x,
+            (Op::Arith(Arith::Mul),[y,x]) => y * x,
+            (Op::Arith(Arith::Div),[y,x]) if !x.is_zero() && (y % x).is_zero() => y / x,
+            (Op::Exp2(Exp2::Pow),[y,x]) => match x.to_u32() {
+                Some(e) if y.bits() * e as u64 <= MAX_BIG_INTEGER_BITS => y.pow(e),
+                _ => return Ok(None)
+            },
+            _ => return Ok(None)
+        };
+        self.integer_result(result).map(Some)
+    }
+    /// An exact integer result: a word in programmer mode, a fraction in
+    /// fraction mode, otherwise this backend's type while it holds the
+    /// integer exactly and a big integer beyond.
+    fn integer_result(&self, n: BigInt) -> Result<Value,CalcError> {
+        Ok(match (self,n.to_i128()) {
+            (Backend::Integer(ctx),Some(i)) => Value::Integer(ctx.fit(i)?),
+            (Backend::Integer(_),None) => return Err(CalcError::Overflow),
+            (Backend::Rational,_) => Value::Rational(BigRational::from_integer(n)),
+            (Backend::Float,Some(i)) if i.unsigned_abs() <= 1 << f64::MANTISSA_DIGITS => Value::Float(i as f64),
+            (Backend::Decimal(ctx),_) if has_at_most_digits(&n,ctx.precision) => Value::Decimal(BigDecimal::new(n,0)),
+            _ => Value::BigInt(n)
+        })
+    }
+    /// Applies `f` to a real value, element by element to arrays. Complex
+    /// values, and real ones `f` has no real answer for, go through `cf`.
+    fn map_real(&self, x: Value, f: impl Fn(f64) -> f64, cf: Option<&dyn Fn(Complex64) -> Complex64>) -> Result<Value,CalcError> {
+        Ok(match x {
+            Value::Vector(v) => Value::Vector(v.into_iter().map(&f).collect()),
+            Value::Matrix(m) => Value::Matrix(Matrix::new(m.rows,m.cols,m.data.into_iter().map(&f).collect())),
+            Value::Quantity(..) => return Err(CalcError::IncompatibleUnits),
+            Value::Complex(z) => match cf {
+                Some(cf) => self.demote(cf(z)),
+                None => return Err(no_complex_form())
+            },
+            x => match (f(x.to_f64()),cf) {
+                (r,Some(cf)) if r.is_nan() && !x.is_nan() => self.demote(cf(x.to_complex())),
+                (r,_) => self.coerce(Value::Float(r))
+            }
+        })
+    }
+    fn eval_part(&self, op: Part, args: Vec<Value>) -> Result<Value,CalcError> {
+        match (op,&args[..]) {
+            (Part::Round,[y,x]) => {
+                let places = x.to_integer().and_then(|n| i64::try_from(n).ok()).ok_or_else(|| CalcError::NotAnInteger(x.to_string()))?;
+                let mode = match self {
+                    Backend::Decimal(ctx) => ctx.rounding.into(),
+                    _ => RoundingMode::HalfUp
+                };
+                match y.to_rational().and(y.to_decimal()) {
+                    Some(d) => Ok(self.coerce(Value::Decimal(d.with_scale_round(places,mode)))),
+                    None => self.map_real(y.clone(),|f| match Value::Float(f).to_decimal() {
+                        Some(d) => d.with_scale_round(places,mode).to_f64().unwrap_or(f),
+                        None => f
+                    },None)
+                }
+            },
+            (Part::Mod | Part::IntDiv,[y,x]) => {
+                real(y)?;
+                real(x)?;
+                match (y.to_rational(),x.to_rational()) {
+                    (_,Some(x)) if x.is_zero() => Err(CalcError::DivisionByZero),
+                    (Some(y),Some(x)) => {
+                        let q = (&y / &x).floor();
+                        Ok(self.coerce(Value::Rational(if op == Part::Mod { y - x * q } else { q })))
+                    },
+                    _ => {
+                        let (y,x) = (y.to_f64(),x.to_f64());
+                        if x == 0.0 {
+                            return Err(CalcError::DivisionByZero);
+                        }
+                        let m = match y % x {
+                            m if m != 0.0 && (m < 0.0) != (x < 0.0) => m + x,
+                            m => m
+                        };
+                        Ok(self.coerce(Value::Float(if op == Part::Mod { m } else { ((y - m) / x).round() })))
+                    }
+                }
+            },
+            // Whole results of exact values go through `integer_result`, so
+            // big integers keep every digit; floats stay floats.
+            (_,[x]) => match x.to_rational() {
+                Some(r) if op == Part::Frac => Ok(self.coerce(Value::Rational(r.fract()))),
+                Some(r) if !matches!(x,Value::Float(_)) => self.integer_result(match op {
+                    Part::Floor => r.floor(),
+                    Part::Ceil => r.ceil(),
+                    Part::Trunc => r.trunc(),
+                    _ => r.signum()
+                }.to_integer()),
+                _ => match op {
+                    Part::Floor => self.map_real(x.clone(),f64::floor,None),
+                    Part::Ceil => self.map_real(x.clone(),f64::ceil,None),
+                    Part::Trunc => self.map_real(x.clone(),f64::trunc,None),
+                    Part::Frac => self.map_real(x.clone(),f64::fract,None),
+                    _ => self.map_real(x.clone(),|f| if f == 0.0 { 0.0 } else { f.signum() },Some(&|z: Complex64| if z.is_zero() { z } else { z / z.norm() }))
+                }
+            },
+            _ => unreachable!("arity checked by CalcUnit")
+        }
+    }
+    /// Percentages through `eval`, so decimals and fractions stay exact.
+    /// Y is handed back below the result.
+    fn eval_percent(&self, op: PercentOp, args: Vec<Value>, stdout: &mut Vec<u8>) -> Result<Vec<Value>,CalcError> {
+        let mut ar = |op: Arith, y: Value, x: Value| -> Result<Value,CalcError> {
+            Ok(self.eval(Op::Arith(op),vec![y,x],stdout)?.pop().unwrap())
+        };
+        let hundred = || self.coerce(Value::Integer(100));
+        let (y,x) = (args[0].clone(),args[1].clone());
+        let result = match op {
+            PercentOp::Percent => {
+                let yx = ar(Arith::Mul,y.clone(),x)?;
+                ar(Arith::Div,yx,hundred())?
+            },
+            PercentOp::Change => {
+                let diff = ar(Arith::Sub,x,y.clone())?;
+                let scaled = ar(Arith::Mul,diff,hundred())?;
+                ar(Arith::Div,scaled,y.clone())?
+            },
+            PercentOp::OfTotal => {
+                let scaled = ar(Arith::Mul,x,hundred())?;
+                ar(Arith::Div,scaled,y.clone())?
+            },
+            PercentOp::Markup => {
+                let yx = ar(Arith::Mul,y.clone(),x)?;
+                let markup = ar(Arith::Div,yx,hundred())?;
+                ar(Arith::Add,y.clone(),markup)?
+            },
+            PercentOp::Margin => {
+                let scaled = ar(Arith::Mul,y.clone(),hundred())?;
+                let rest = ar(Arith::Sub,hundred(),x)?;
+                ar(Arith::Div,scaled,rest)?
+            },
+            PercentOp::MarginOf => {
+                let diff = ar(Arith::Sub,x.clone(),y.clone())?;
+                let scaled = ar(Arith::Mul,diff,hundred())?;
+                ar(Arith::Div,scaled,x)?
+            }
+        };
+        Ok(vec![y,result])
+    }
+    /// Reduces or rearranges `args` (X last). Sums and means go through
+    /// `eval`, so they stay exact wherever addition does.
+    fn eval_whole(&self, op: WholeOp, args: Vec<Value>, stdout: &mut Vec<u8>) -> Result<Vec<Value>,CalcError> {
+        if args.is_empty() {
+            return Err(CalcError::StackUnderflow { needed: 1, available: 0 });
+        }
+        let mut binary = |op: Arith, y: Value, x: Value| -> Result<Value,CalcError> {
+            Ok(self.eval(Op::Arith(op),vec![y,x],stdout)?.pop().unwrap())
+        };
+        // A word too small to hold the count cannot divide by it.
+        let count = |n: usize| match self {
+            Backend::Integer(ctx) if n as i128 > ctx.max() => Err(CalcError::Overflow),
+            _ => Ok(self.coerce(Value::Integer(n as i128)))
+        };
+        let sorted = |mut args: Vec<Value>| -> Result<Vec<Value>,CalcError> {
+            if args.iter().any(|v| v.compare(v).is_none()) {
+                return Err(CalcError::Unordered);
+            }
+            args.sort_by(|a,b| a.compare(b).unwrap_or(Ordering::Equal));
+            Ok(args)
+        };
+        Ok(match op {
+            WholeOp::Sum | WholeOp::Product | WholeOp::Mean => {
+                let n = args.len();
+                let step = if op == WholeOp::Product { Arith::Mul } else { Arith::Add };
+                let mut args = args.into_iter();
+                let first = args.next().unwrap();
+                let total = args.try_fold(first,|acc,v| binary(step,acc,v))?;
+                match op {
+                    WholeOp::Mean => vec![binary(Arith::Div,total,count(n)?)?],
+                    _ => vec![total]
+                }
+            },
+            WholeOp::Median => {
+                let args = sorted(args)?;
+                let n = args.len();
+                let upper = args[n / 2].clone();
+                match n % 2 {
+                    1 => vec![upper],
+                    _ => {
+                        let sum = binary(Arith::Add,args[n / 2 - 1].clone(),upper)?;
+                        vec![binary(Arith::Div,sum,count(2)?)?]
+                    }
+                }
+            },
+            WholeOp::SortAsc => sorted(args)?,
+            WholeOp::SortDesc => {
+                let mut args = sorted(args)?;
+                args.reverse();
+                args
+            },
+            WholeOp::Min => vec![sorted(args)?.swap_remove(0)],
+            WholeOp::Max => vec![sorted(args)?.pop().unwrap()],
+            WholeOp::Unique => {
+                let mut kept: Vec<Value> = vec![];
+                for v in args {
+                    if !kept.iter().any(|k| *k == v || k.compare(&v) == Some(Ordering::Equal)) {
+                        kept.push(v);
+                    }
+                }
+                kept
+            }
+        })
+    }
+}
+
+/// Largest big integer a power may produce, about 30,000 digits.
+pub const MAX_BIG_INTEGER_BITS: u64 = 100_000;
+
+/// Whether `n` has no more than `digits` decimal digits, told from its bit
+/// length unless it is within a digit of the limit.
+fn has_at_most_digits(n: &BigInt, digits: u64) -> bool {
+    let bits = n.bits().max(1) as f64;
+    // 2^(bits - 1) <= |n| < 2^bits
+    let fewest = ((bits - 1.0) * std::f64::consts::LOG10_2) as u64 + 1;
+    let most = (bits * std::f64::consts::LOG10_2) as u64 + 1;
+    match (most <= digits,fewest > digits) {
+        (true,_) => true,
+        (_,true) => false,
+        _ => *n.magnitude() < BigUint::from(10u8).pow(digits as u32)
+    }
+}
+
+/// Floats beyond this may already have lost digits, so they are not taken
+/// for the integers they look like.
+const MAX_EXACT_FLOAT: f64 = (1u64 << f64::MANTISSA_DIGITS) as f64;
+
+/// The exact integer a finite, integral real stands for.
+fn integral(v: &Value) -> Option<BigInt> {
+    match v {
+        Value::Float(f) if !f.is_finite() || f.fract() != 0.0 || f.abs() > MAX_EXACT_FLOAT => None,
+        Value::Complex(_) | Value::Quantity(..) | Value::Vector(_) | Value::Matrix(_) => None,
+        v => number_theory::to_big_integer(v).ok()
+    }
+}
+
+fn no_complex_form() -> CalcError {
+    CalcError::Engine("Operation has no complex form".to_string())
+}
+
+/// Fails for values that are not a single real number.
+fn real(v: &Value) -> Result<(),CalcError> {
+    match v {
+        Value::Complex(_) => Err(no_complex_form()),
+        Value::Quantity(..) => Err(CalcError::IncompatibleUnits),
+        Value::Vector(_) | Value::Matrix(_) => Err(CalcError::ShapeMismatch),
+        _ => Ok(())
+    }
+}
+
+/// Word arithmetic on integers, falling back to floats for everything else
+/// or when an argument is not an integer.
+fn integer_eval(ctx: &IntContext, op: Op, args: &[Value], stdout: &mut Vec<u8>) -> Result<Vec<Value>,CalcError> {
+    let ints = args.iter().map(|v| match v {
+        Value::Integer(i) => Some(*i),
+        _ => None
+    }).collect::<Option<Vec<_>>>();
+    let result = match (op,ints.as_deref()) {
+        (Op::Arith(a),Some(&[y,x])) => ctx.arith(a,y,x)?,
+        (Op::Exp2(Exp2::Pow),Some(&[y,x])) if (0..=u32::MAX as i128).contains(&x) => ctx.pow(y,x as u32)?,
+        _ => return Ok(float_eval(op,args,stdout)?.into_iter().map(Value::Float).collect())
+    };
+    Ok(vec![Value::Integer(result)])
+}
+
+/// Exact arithmetic on fractions, falling back to floats for everything else
+/// or when an argument is already a float.
+fn rational_eval(op: Op, args: &[Value], stdout: &mut Vec<u8>) -> Result<Vec<Value>,CalcError> {
+    let rationals = args.iter().map(Value::to_rational).collect::<Option<Vec<_>>>();
+    let result = match (op,rationals.as_deref()) {
+        (Op::Arith(a),Some([y,x])) => match a {
+            Arith::Add => y + x,
+            Arith::Sub => y - x,
+            Arith::Mul => y * x,
+            Arith::Div => {
+                if x.is_zero() {
+                    return Err(CalcError::DivisionByZero);
+                }
+                y / x
+            }
+        },
+        (Op::Exp2(Exp2::Pow),Some([y,x])) if x.is_integer() && x.to_integer().to_i32().map_or(false,|e| e.abs() <= 10_000) => {
+            let e = x.to_integer().to_i32().unwrap();
+            if e < 0 && y.is_zero() {
+                return Err(CalcError::DivisionByZero);
+            }
+            y.pow(e)
+        },
+        _ => return Ok(float_eval(op,args,stdout)?.into_iter().map(Value::Float).collect())
+    };
+    Ok(vec![Value::Rational(result)])
+}
+
+/// Runs `op` through rpncalc on the `f64` view of `args`.
+fn float_eval(op: Op, args: &[Value], stdout: &mut Vec<u8>) -> Result<Vec<f64>,CalcError> {
+    use rpncalc::Command;
+    let mut stack = args.iter().map(Value::to_f64).collect::<Vec<_>>();
+    let mut out = std::io::Cursor::new(vec![]);
+    let res = op.command_or_op().comm(&mut stack,std::io::empty(),&mut out);
+    stdout.extend(out.into_inner());
+    match res {
+        Ok(msg) => {
+            if let Some(msg) = msg {
+                stdout.extend(msg.into_bytes());
+            }
+            Ok(stack)
+        },
+        Err(e) => Err(CalcError::Engine(format!("{e:?}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word() -> Backend {
+        Backend::Integer(IntContext::default())
+    }
+
+    #[test]
+    fn words_take_only_integral_values() {
+        assert_eq!(word().coerce(Value::Float(3.0)),Value::Integer(3));
+        assert_eq!(word().coerce(Value::Decimal(BigDecimal::from(-7))),Value::Integer(-7));
+        assert_eq!(word().coerce(Value::Rational(BigRational::from_integer(12.into()))),Value::Integer(12));
+    }
+
+    #[test]
+    fn words_leave_fractional_values_as_floats() {
+        assert_eq!(word().coerce(Value::Float(2.5)),Value::Float(2.5));
+        assert_eq!(word().coerce(Value::Float(-0.75)),Value::Float(-0.75));
+        assert_eq!(word().coerce(Value::Decimal(BigDecimal::from_str("1.25").unwrap())),Value::Float(1.25));
+        assert_eq!(word().coerce(Value::Rational(BigRational::new(1.into(),2.into()))),Value::Float(0.5));
+    }
+    #[test]
+    fn means_refuse_counts_beyond_the_word() {
+        let byte = Backend::Integer(IntContext { bits: 8, signed: true, overflow: Overflow::Wrap });
+        let mean = |n: usize| byte.eval_native(Native::Whole(WholeOp::Mean),vec![Value::Integer(1); n],&mut vec![]);
+        assert_eq!(mean(127),Ok(vec![Value::Integer(1)]));
+        assert_eq!(mean(200),Err(CalcError::Overflow));
+    }
+    #[test]
+    fn whole_parts_of_big_integers_keep_their_digits() {
+        let big = BigInt::from(3).pow(100);
+        for backend in [Backend::Float,Backend::Decimal(DecimalContext::default())] {
+            for op in [Part::Floor,Part::Ceil,Part::Trunc] {
+                assert_eq!(backend.eval_part(op,vec![Value::BigInt(big.clone())]),Ok(Value::BigInt(big.clone())));
+            }
+            assert_eq!(backend.eval_part(Part::Sign,vec![Value::BigInt(-big.clone())]),backend.integer_result(BigInt::from(-1)));
+        }
+        assert_eq!(Backend::Float.eval_part(Part::Floor,vec![Value::Float(-2.5)]),Ok(Value::Float(-3.0)));
+        assert_eq!(word().eval_part(Part::Floor,vec![Value::Float(-2.5)]),Ok(Value::Integer(-3)));
+    }
+    fn float_of(results: Vec<Value>) -> f64 {
+        match results[..] {
+            [Value::Float(f)] => f,
+            _ => panic!("expected a single float, got {results:?}")
+        }
+    }
+
+    #[test]
+    fn hyperbolics_keep_fractions_in_words() {
+        let sinh = word().eval_native(Native::Hyperbolic(Hyperbolic::Sinh),vec![Value::Float(0.5)],&mut vec![]).unwrap();
+        assert!((float_of(sinh) - 0.5f64.sinh()).abs() < 1e-15);
+    }
+
+    #[test]
+    fn exponentials_keep_fractions_in_words() {
+        let exp = word().eval_native(Native::Power(Power::E),vec![Value::Integer(1)],&mut vec![]).unwrap();
+        assert!((float_of(exp) - std::f64::consts::E).abs() < 1e-15);
+    }
+
+    #[test]
+    fn gamma_keeps_fractions_in_words() {
+        let gamma = word().eval_native(Native::NumberTheory(NumberOp::Gamma),vec![Value::Float(0.5)],&mut vec![]).unwrap();
+        assert!((float_of(gamma) - std::f64::consts::PI.sqrt()).abs() < 1e-12);
+    }
+    #[test]
+    fn floats_past_two_to_the_53_are_not_promoted() {
+        let big = Value::Float(2f64.powi(60));
+        let args = [big.clone(),Value::Float(1.0)];
+        assert_eq!(Backend::Float.big_integer_eval(Op::Arith(Arith::Add),&args),Ok(None));
+        let small = [Value::Float(2f64.powi(40)),Value::Float(2f64.powi(40))];
+        assert_eq!(Backend::Float.big_integer_eval(Op::Arith(Arith::Mul),&small),Ok(Some(Value::BigInt(BigInt::one() << 80))));
+    }
+
+    #[test]
+    fn typed_integers_keep_every_digit() {
+        let typed = Backend::Float.parse("100000000000000000001").unwrap();
+        assert_eq!(typed,Value::BigInt(BigInt::from_str("100000000000000000001").unwrap()));
+        let sum = Backend::Float.big_integer_eval(Op::Arith(Arith::Add),&[typed,Value::Float(1.0)]).unwrap();
+        assert_eq!(sum,Some(Value::BigInt(BigInt::from_str("100000000000000000002").unwrap())));
+        assert_eq!(Backend::Float.parse("42").unwrap(),Value::Float(42.0));
+    }
+
+    #[test]
+    fn digits_are_counted_from_bits() {
+        for (n,digits) in [(0,1),(9,1),(10,2),(99,2),(512,3),(999,3),(1000,4),(1023,4),(1024,4)] {
+            let n = BigInt::from(n);
+            assert!(has_at_most_digits(&n,digits),"{n}");
+            assert!(!has_at_most_digits(&n,digits - 1) || digits == 1,"{n}");
+            assert!(has_at_most_digits(&-n,digits));
+        }
+        let decimals = Backend::Decimal(DecimalContext { precision: 3, ..DecimalContext::default() });
+        assert_eq!(decimals.integer_result(BigInt::from(999)),Ok(Value::Decimal(BigDecimal::from(999))));
+        assert_eq!(decimals.integer_result(BigInt::from(1000)),Ok(Value::BigInt(BigInt::from(1000))));
+    }
+}
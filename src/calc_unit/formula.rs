@@ -0,0 +1,369 @@
+use super::ops::{CommOrOp,FormulaOp};
+use super::{CalcUnit,CalcError,CalcResult,Value,Backend};
+
+/// Steps the root finder may take.
+const MAX_ITERATIONS: usize = 500;
+/// Times a secant step is halved looking for a point the formula is
+/// defined at.
+const MAX_SHRINKS: usize = 60;
+/// Most pieces an integral is split into before giving up.
+const MAX_INTERVALS: usize = 500;
+
+/// Abscissae of the 15-point Kronrod rule on [-1, 1], outermost first. Every
+/// other one is also a node of the embedded 7-point Gauss rule.
+const XGK: [f64; 8] = [
+    0.991455371120812639206854697526329, 0.949107912342758524526189684047851,
+    0.864864423359769072789712788640926, 0.741531185599394439863864773280788,
+    0.586087235467691130294144845693013, 0.405845151377397166906606412076961,
+    0.207784955007898467600689403773245, 0.0
+];
+const WGK: [f64; 8] = [
+    0.022935322010529224963732008058970, 0.063092092629978553290700663189204,
+    0.104790010322250183839876322541518, 0.140653259715525918745189590510238,
+    0.169004726639267902826583426598550, 0.190350578064785409913256402421014,
+    0.204432940075298892414161999234649, 0.209482141084727828012999174891714
+];
+const WG: [f64; 4] = [
+    0.129484966168869693270611432679082, 0.279705391489276667901467771423780,
+    0.381830050505118944950369775488975, 0.417959183673469387755102040816327
+];
+
+/// One recorded step of a formula.
+#[derive(Clone,PartialEq)]
+pub enum Step {
+    Push(Value),
+    /// An op, with the number of levels it took when it works on the whole
+    /// stack.
+    Run(CommOrOp,Option<usize>)
+}
+
+/// A function of x: the steps that turn x in X into the result in X.
+#[derive(Clone,PartialEq,Default)]
+pub struct Formula {
+    pub steps: Vec<Step>,
+    /// The backend the steps were recorded in, and are replayed in.
+    pub backend: Backend
+}
+
+impl Formula {
+    /// Runs the formula on a scratch stack holding only `x`.
+    pub fn eval(&self, x: f64) -> CalcResult<f64> {
+        let mut unit = CalcUnit::default();
+        unit.set_backend(self.backend);
+        unit.push(Value::Float(x));
+        for step in &self.steps {
+            match step {
+                Step::Push(v) => unit.push(v.clone()),
+                Step::Run(op,top) => { unit.run_op_top(*op,*top).1?; }
+            }
+        }
+        let y = unit.stack.pop().ok_or(CalcError::StackUnderflow { needed: 1, available: 0 })?;
+        match y.to_f64() {
+            y if y.is_finite() => Ok(y),
+            _ => Err(CalcError::OutOfDomain)
+        }
+    }
+    /// Brent's method, closing in on the root between `a` and `b`, where the
+    /// formula has opposite signs.
+    fn brent(&self, (mut a,mut fa): (f64,f64), (mut b,mut fb): (f64,f64)) -> CalcResult<f64> {
+        let (mut c,mut fc) = (b,fb);
+        let (mut d,mut e) = (b - a,b - a);
+        for _ in 0..MAX_ITERATIONS {
+            if (fb > 0.0) == (fc > 0.0) {
+                (c,fc) = (a,fa);
+                d = b - a;
+                e = d;
+            }
+            if fc.abs() < fb.abs() {
+                (a,fa) = (b,fb);
+                (b,fb) = (c,fc);
+                (c,fc) = (a,fa);
+            }
+            let tol = 2.0 * f64::EPSILON * b.abs() + f64::MIN_POSITIVE;
+            let m = (c - b) / 2.0;
+            if m.abs() <= tol || fb == 0.0 {
+                return Ok(b);
+            }
+            if e.abs() >= tol && fa.abs() > fb.abs() {
+                // Secant or inverse quadratic interpolation, kept only when
+                // it falls well inside the bracket.
+                let s = fb / fa;
+                let (mut p,mut q) = match a == c {
+                    true => (2.0 * m * s,1.0 - s),
+                    false => {
+                        let (q,r) = (fa / fc,fb / fc);
+                        (s * (2.0 * m * q * (q - r) - (b - a) * (r - 1.0)),(q - 1.0) * (r - 1.0) * (s - 1.0))
+                    }
+                };
+                if p > 0.0 {
+                    q = -q;
+                }
+                p = p.abs();
+                if 2.0 * p < (3.0 * m * q - (tol * q).abs()).min((e * q).abs()) {
+                    e = d;
+                    d = p / q;
+                } else {
+                    d = m;
+                    e = d;
+                }
+            } else {
+                d = m;
+                e = d;
+            }
+            (a,fa) = (b,fb);
+            b += if d.abs() > tol { d } else { tol.copysign(m) };
+            fb = self.eval(b)?;
+        }
+        Err(CalcError::NoConvergence)
+    }
+    /// Secant steps from `x0` and `x1` until they settle on a root, handing
+    /// over to Brent's method as soon as a sign change brackets one.
+    fn root(&self, x0: f64, x1: f64) -> CalcResult<f64> {
+        let (mut a,mut fa) = (x0,self.eval(x0)?);
+        let (mut b,mut fb) = (x1,self.eval(x1)?);
+        for _ in 0..MAX_ITERATIONS {
+            if fa == 0.0 {
+                return Ok(a);
+            }
+            if fb == 0.0 {
+                return Ok(b);
+            }
+            if (fa > 0.0) != (fb > 0.0) {
+                return self.brent((a,fa),(b,fb));
+            }
+            if fa == fb {
+                break;
+            }
+            let mut step = -fb * (b - a) / (fb - fa);
+            if !(b + step).is_finite() {
+                break;
+            }
+            // A step too small to move b: a root that touches zero without
+            // crossing it.
+            if b + step == b {
+                return Ok(b);
+            }
+            // A step to where the formula is undefined, say past the end of
+            // a logarithm's domain, is halved until it lands back inside.
+            let mut shrinks = 0;
+            let f_next = loop {
+                match self.eval(b + step) {
+                    Ok(f) => break f,
+                    Err(e) if shrinks == MAX_SHRINKS || b + step / 2.0 == b => return Err(e),
+                    Err(_) => {
+                        step /= 2.0;
+                        shrinks += 1;
+                    }
+                }
+            };
+            (a,fa) = (b,fb);
+            (b,fb) = (b + step,f_next);
+        }
+        Err(CalcError::NoConvergence)
+    }
+    /// The 15-point Gauss-Kronrod estimate of the integral over [a, b], and
+    /// how far it is from the 7-point Gauss one as an estimate of its error.
+    fn kronrod(&self, a: f64, b: f64) -> CalcResult<(f64,f64)> {
+        let (c,h) = ((a + b) / 2.0,(b - a) / 2.0);
+        let fc = self.eval(c)?;
+        let (mut k,mut g) = (fc * WGK[7],fc * WG[3]);
+        for (i,(x,w)) in XGK.iter().zip(WGK).take(7).enumerate() {
+            let sum = self.eval(c - h * x)? + self.eval(c + h * x)?;
+            k += w * sum;
+            if i % 2 == 1 {
+                g += WG[i / 2] * sum;
+            }
+        }
+        Ok((k * h,((k - g) * h).abs()))
+    }
+    /// Integral over [a, b] and its error estimate, halving the piece with
+    /// the largest error until the total is within `tol`.
+    fn integral(&self, a: f64, b: f64, tol: f64) -> CalcResult<(f64,f64)> {
+        let (value,error) = self.kronrod(a,b)?;
+        let mut pieces = vec![(a,b,value,error)];
+        while pieces.len() < MAX_INTERVALS {
+            let (value,error) = pieces.iter().fold((0.0,0.0),|(v,e),p| (v + p.2,e + p.3));
+            if error <= tol {
+                return Ok((value,error));
+            }
+            let worst = (0..pieces.len()).max_by(|&i,&j| pieces[i].3.total_cmp(&pieces[j].3)).unwrap();
+            let (a,b,_,_) = pieces.swap_remove(worst);
+            let m = (a + b) / 2.0;
+            for (a,b) in [(a,m),(m,b)] {
+                let (value,error) = self.kronrod(a,b)?;
+                pieces.push((a,b,value,error));
+            }
+        }
+        Err(CalcError::NoConvergence)
+    }
+    /// Derivative at `x` and its error estimate, by Ridders' extrapolation
+    /// of central differences over shrinking steps.
+    fn derivative(&self, x: f64) -> CalcResult<(f64,f64)> {
+        const SHRINK: f64 = 1.4;
+        const STEPS: usize = 10;
+        let mut h = 0.1 * (x.abs() + 1.0);
+        let mut table = [[0.0; STEPS]; STEPS];
+        let (mut best,mut error,mut rounding) = (f64::NAN,f64::INFINITY,0.0);
+        for i in 0..STEPS {
+            let (up,down) = (self.eval(x + h)?,self.eval(x - h)?);
+            table[0][i] = (up - down) / (2.0 * h);
+            // Rounding in f and in x ± h, which no extrapolation takes out.
+            let noise = f64::EPSILON * (up.abs() + down.abs() + 2.0 * (x * table[0][i]).abs()) / h;
+            let mut factor = SHRINK * SHRINK;
+            for j in 1..=i {
+                table[j][i] = (table[j - 1][i] * factor - table[j - 1][i - 1]) / (factor - 1.0);
+                factor *= SHRINK * SHRINK;
+                let e = (table[j][i] - table[j - 1][i]).abs().max((table[j][i] - table[j - 1][i - 1]).abs());
+                if e <= error {
+                    (best,error,rounding) = (table[j][i],e,noise);
+                }
+            }
+            // Once the higher orders get worse, rounding has taken over.
+            if i > 0 && (table[i][i] - table[i - 1][i - 1]).abs() >= 2.0 * error {
+                break;
+            }
+            h /= SHRINK;
+        }
+        match best.is_finite() && error.is_finite() {
+            true => Ok((best,error.max(rounding))),
+            false => Err(CalcError::NoConvergence)
+        }
+    }
+    /// Runs `op` on `args` (X last) and returns the values to push.
+    pub fn eval_op(&self, op: FormulaOp, args: &[f64]) -> CalcResult<Vec<f64>> {
+        if self.steps.is_empty() {
+            return Err(CalcError::NoFormula);
+        }
+        Ok(match (op,args) {
+            (FormulaOp::Solve,&[x]) => vec![self.root(x,x + 1e-3 * (x.abs() + 1.0))?],
+            (FormulaOp::SolveBetween,&[y,x]) if y == x => return Err(CalcError::OutOfDomain),
+            (FormulaOp::SolveBetween,&[y,x]) => vec![self.root(y,x)?],
+            (FormulaOp::Integrate,&[_,_,tol]) if tol <= 0.0 => return Err(CalcError::OutOfDomain),
+            (FormulaOp::Integrate,&[a,b,tol]) => {
+                let (value,error) = self.integral(a,b,tol)?;
+                vec![error,value]
+            },
+            (FormulaOp::Derivative,&[x]) => {
+                let (value,error) = self.derivative(x)?;
+                vec![error,value]
+            },
+            _ => unreachable!("arity checked by CalcUnit")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc_unit::ops::{Op,Arith,Exp2,Exp,Trig,Command,Native,Power};
+
+    fn run(op: Op) -> Step {
+        Step::Run(CommOrOp::Op(op),None)
+    }
+
+    fn push(f: f64) -> Step {
+        Step::Push(Value::Float(f))
+    }
+
+    fn formula(steps: Vec<Step>) -> Formula {
+        Formula { steps, ..Formula::default() }
+    }
+
+    /// x² − 2
+    fn square_minus_two() -> Formula {
+        formula(vec![push(2.0),run(Op::Exp2(Exp2::Pow)),push(2.0),run(Op::Arith(Arith::Sub))])
+    }
+
+    #[test]
+    fn secant_finds_a_root_from_a_guess() {
+        let root = square_minus_two().eval_op(FormulaOp::Solve,&[1.0]).unwrap();
+        assert!((root[0] - 2f64.sqrt()).abs() < 1e-12,"{root:?}");
+        let root = square_minus_two().eval_op(FormulaOp::Solve,&[-5.0]).unwrap();
+        assert!((root[0] + 2f64.sqrt()).abs() < 1e-12,"{root:?}");
+    }
+
+    #[test]
+    fn brent_closes_in_on_a_bracketed_root() {
+        // x³ − 2x − 5, Newton's own example.
+        let cubic = formula(vec![
+            Step::Run(CommOrOp::Command(Command::Dup),None),
+            push(3.0),run(Op::Exp2(Exp2::Pow)),
+            Step::Run(CommOrOp::Command(Command::Swap),None),
+            push(2.0),run(Op::Arith(Arith::Mul)),
+            run(Op::Arith(Arith::Sub)),
+            push(5.0),run(Op::Arith(Arith::Sub))
+        ]);
+        let (a,b) = (2.0,3.0);
+        let root = cubic.brent((a,cubic.eval(a).unwrap()),(b,cubic.eval(b).unwrap())).unwrap();
+        assert!((root - 2.0945514815423265).abs() < 1e-12,"{root}");
+        let root = cubic.eval_op(FormulaOp::SolveBetween,&[a,b]).unwrap();
+        assert!((root[0] - 2.0945514815423265).abs() < 1e-12,"{root:?}");
+    }
+
+    #[test]
+    fn secant_steps_back_from_undefined_points() {
+        // ln x + 3: the first secant step lands near x = −2.
+        let log = formula(vec![run(Op::Exp(Exp::LogE)),push(3.0),run(Op::Arith(Arith::Add))]);
+        assert_eq!(log.eval(-2.0),Err(CalcError::OutOfDomain));
+        let root = log.eval_op(FormulaOp::Solve,&[1.0]).unwrap();
+        assert!((root[0] - (-3f64).exp()).abs() < 1e-12,"{root:?}");
+    }
+
+    #[test]
+    fn solving_between_one_point_is_out_of_domain() {
+        assert_eq!(square_minus_two().eval_op(FormulaOp::SolveBetween,&[1.0,1.0]),Err(CalcError::OutOfDomain));
+    }
+
+    #[test]
+    fn formulas_replay_in_their_backend() {
+        // x / 2 truncates in programmer mode, for integral x.
+        let half = Formula {
+            steps: vec![Step::Push(Value::Integer(2)),run(Op::Arith(Arith::Div))],
+            backend: Backend::Integer(Default::default())
+        };
+        assert_eq!(half.eval(3.0),Ok(1.0));
+        assert_eq!(formula(half.steps.clone()).eval(3.0),Ok(1.5));
+    }
+    #[test]
+    fn integrates_sine_over_half_a_turn() {
+        let sine = formula(vec![run(Op::Trig(Trig::Sin))]);
+        let out = sine.eval_op(FormulaOp::Integrate,&[0.0,std::f64::consts::PI,1e-10]).unwrap();
+        let (error,value) = (out[0],out[1]);
+        assert!(error <= 1e-10,"{error}");
+        assert!((value - 2.0).abs() <= 1e-10,"{value}");
+    }
+
+    #[test]
+    fn kronrod_is_exact_for_polynomials() {
+        // The 15-point rule integrates up to degree 22 exactly, the 7-point
+        // Gauss rule up to degree 13.
+        let (value,error) = square_minus_two().kronrod(0.0,3.0).unwrap();
+        assert!((value - 3.0).abs() < 1e-13,"{value}");
+        assert!(error < 1e-13,"{error}");
+    }
+
+    #[test]
+    fn derivative_of_the_exponential_is_itself() {
+        let exp = formula(vec![Step::Run(CommOrOp::Native(Native::Power(Power::E)),None)]);
+        let out = exp.eval_op(FormulaOp::Derivative,&[1.0]).unwrap();
+        let (error,value) = (out[0],out[1]);
+        assert!((value - std::f64::consts::E).abs() <= error,"{value} ± {error}");
+        assert!(error < 1e-10,"{error}");
+    }
+
+    #[test]
+    fn integrating_needs_a_positive_tolerance() {
+        let out = square_minus_two().eval_op(FormulaOp::Integrate,&[0.0,1.0,0.0]);
+        assert_eq!(out,Err(CalcError::OutOfDomain));
+        let out = square_minus_two().eval_op(FormulaOp::Integrate,&[0.0,1.0,-1e-6]);
+        assert_eq!(out,Err(CalcError::OutOfDomain));
+    }
+
+    #[test]
+    fn an_empty_formula_is_no_formula() {
+        let cases: [(FormulaOp,&[f64]); 3] = [(FormulaOp::Solve,&[1.0]),(FormulaOp::Integrate,&[0.0,1.0,1e-6]),(FormulaOp::Derivative,&[1.0])];
+        for (op,args) in cases {
+            assert_eq!(Formula::default().eval_op(op,args),Err(CalcError::NoFormula));
+        }
+    }
+}
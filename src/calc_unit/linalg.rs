@@ -0,0 +1,203 @@
+use super::value::Value;
+use super::ops::{Op,Arith,LinalgOp};
+use super::CalcError;
+
+/// A dense matrix of floats, stored row by row.
+#[derive(Clone,PartialEq,Debug)]
+pub struct Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<f64>
+}
+
+impl Matrix {
+    pub fn new(rows: usize, cols: usize, data: Vec<f64>) -> Matrix {
+        assert_eq!(rows * cols,data.len(),"matrix data does not match its shape");
+        Matrix { rows, cols, data }
+    }
+    fn identity(n: usize) -> Matrix {
+        let mut m = Matrix::new(n,n,vec![0.0; n * n]);
+        for i in 0..n {
+            m.data[i * n + i] = 1.0;
+        }
+        m
+    }
+    /// A single-column matrix.
+    fn column(v: &[f64]) -> Matrix {
+        Matrix::new(v.len(),1,v.to_vec())
+    }
+    pub fn row(&self, r: usize) -> &[f64] {
+        &self.data[r * self.cols..(r + 1) * self.cols]
+    }
+    fn get(&self, r: usize, c: usize) -> f64 {
+        self.data[r * self.cols + c]
+    }
+    fn transpose(&self) -> Matrix {
+        let data = (0..self.cols).flat_map(|c| (0..self.rows).map(move |r| (r,c))).map(|(r,c)| self.get(r,c)).collect();
+        Matrix::new(self.cols,self.rows,data)
+    }
+    fn mul(&self, other: &Matrix) -> Result<Matrix,CalcError> {
+        if self.cols != other.rows {
+            return Err(CalcError::ShapeMismatch);
+        }
+        let data = (0..self.rows).flat_map(|r| (0..other.cols).map(move |c| (r,c)))
+            .map(|(r,c)| (0..self.cols).map(|k| self.get(r,k) * other.get(k,c)).sum())
+            .collect();
+        Ok(Matrix::new(self.rows,other.cols,data))
+    }
+    /// Gauss-Jordan elimination of `self` alongside `rhs`, with partial
+    /// pivoting. Leaves `rhs` holding `self⁻¹·rhs` and returns the determinant.
+    fn eliminate(&self, rhs: &mut Matrix) -> Result<f64,CalcError> {
+        if self.rows != self.cols {
+            return Err(CalcError::ShapeMismatch);
+        }
+        let n = self.rows;
+        let mut a = self.clone();
+        let mut det = 1.0;
+        for col in 0..n {
+            let pivot = (col..n).max_by(|&i,&j| a.get(i,col).abs().total_cmp(&a.get(j,col).abs())).unwrap();
+            if a.get(pivot,col) == 0.0 {
+                return Err(CalcError::SingularMatrix);
+            }
+            if pivot != col {
+                swap_rows(&mut a,pivot,col);
+                swap_rows(rhs,pivot,col);
+                det = -det;
+            }
+            let p = a.get(col,col);
+            det *= p;
+            for row in 0..n {
+                if row == col {
+                    continue;
+                }
+                let f = a.get(row,col) / p;
+                for c in 0..n {
+                    let d = f * a.get(col,c);
+                    a.data[row * n + c] -= d;
+                }
+                for c in 0..rhs.cols {
+                    let d = f * rhs.get(col,c);
+                    rhs.data[row * rhs.cols + c] -= d;
+                }
+            }
+        }
+        for row in 0..n {
+            let p = a.get(row,row);
+            for c in 0..rhs.cols {
+                rhs.data[row * rhs.cols + c] /= p;
+            }
+        }
+        Ok(det)
+    }
+    fn det(&self) -> Result<f64,CalcError> {
+        match self.eliminate(&mut Matrix::new(self.rows,0,vec![])) {
+            Err(CalcError::SingularMatrix) => Ok(0.0),
+            res => res
+        }
+    }
+}
+
+fn swap_rows(m: &mut Matrix, i: usize, j: usize) {
+    for c in 0..m.cols {
+        m.data.swap(i * m.cols + c,j * m.cols + c);
+    }
+}
+
+enum Operand {
+    Scalar(f64),
+    Vector(Vec<f64>),
+    Matrix(Matrix)
+}
+
+impl Operand {
+    fn of(v: &Value) -> Result<Operand,CalcError> {
+        Ok(match v {
+            Value::Vector(v) => Operand::Vector(v.clone()),
+            Value::Matrix(m) => Operand::Matrix(m.clone()),
+            Value::Quantity(..) => return Err(CalcError::IncompatibleUnits),
+            v if v.is_nan() => Operand::Scalar(f64::NAN),
+            v => match v.to_f64() {
+                x if x.is_nan() => return Err(CalcError::ShapeMismatch),
+                x => Operand::Scalar(x)
+            }
+        })
+    }
+    fn into_value(self) -> Value {
+        match self {
+            Operand::Scalar(x) => Value::Float(x),
+            Operand::Vector(v) => Value::Vector(v),
+            Operand::Matrix(m) => Value::Matrix(m)
+        }
+    }
+    fn map(&self, f: &mut dyn FnMut(f64) -> Result<f64,CalcError>) -> Result<Operand,CalcError> {
+        let map = |xs: &[f64], f: &mut dyn FnMut(f64) -> Result<f64,CalcError>| xs.iter().map(|&x| f(x)).collect::<Result<Vec<_>,_>>();
+        Ok(match self {
+            Operand::Scalar(x) => Operand::Scalar(f(*x)?),
+            Operand::Vector(v) => Operand::Vector(map(v,f)?),
+            Operand::Matrix(m) => Operand::Matrix(Matrix::new(m.rows,m.cols,map(&m.data,f)?))
+        })
+    }
+}
+
+/// Applies `f` element by element, repeating a scalar against every element
+/// of the other side.
+fn zip(y: &Operand, x: &Operand, f: &mut dyn FnMut(f64,f64) -> Result<f64,CalcError>) -> Result<Operand,CalcError> {
+    let zip = |a: &[f64], b: &[f64], f: &mut dyn FnMut(f64,f64) -> Result<f64,CalcError>| a.iter().zip(b).map(|(&a,&b)| f(a,b)).collect::<Result<Vec<_>,_>>();
+    match (y,x) {
+        (Operand::Scalar(y),x) => x.map(&mut |x| f(*y,x)),
+        (y,Operand::Scalar(x)) => y.map(&mut |y| f(y,*x)),
+        (Operand::Vector(a),Operand::Vector(b)) if a.len() == b.len() => Ok(Operand::Vector(zip(a,b,f)?)),
+        (Operand::Matrix(a),Operand::Matrix(b)) if (a.rows,a.cols) == (b.rows,b.cols) => Ok(Operand::Matrix(Matrix::new(a.rows,a.cols,zip(&a.data,&b.data,f)?))),
+        _ => Err(CalcError::ShapeMismatch)
+    }
+}
+
+/// Runs `op` on arguments of which at least one is a vector or matrix.
+/// Products involving a matrix follow linear algebra; everything else works
+/// element by element through `scalar_op`.
+pub fn eval(op: Op, args: &[Value], scalar_op: &mut dyn FnMut(Op,&[f64]) -> Result<f64,CalcError>) -> Result<Value,CalcError> {
+    let args = args.iter().map(Operand::of).collect::<Result<Vec<_>,_>>()?;
+    let result = match (op,&args[..]) {
+        (Op::Arith(Arith::Add | Arith::Sub),[Operand::Scalar(_),_]) |
+        (Op::Arith(Arith::Add | Arith::Sub),[_,Operand::Scalar(_)]) => return Err(CalcError::ShapeMismatch),
+        (Op::Arith(Arith::Mul),[Operand::Matrix(a),Operand::Matrix(b)]) => Operand::Matrix(a.mul(b)?),
+        (Op::Arith(Arith::Mul),[Operand::Matrix(a),Operand::Vector(v)]) => Operand::Vector(a.mul(&Matrix::column(v))?.data),
+        (Op::Arith(Arith::Mul),[Operand::Vector(v),Operand::Matrix(a)]) => Operand::Vector(Matrix::column(v).transpose().mul(a)?.data),
+        (_,[y,x]) => zip(y,x,&mut |y,x| scalar_op(op,&[y,x]))?,
+        (_,[x]) => x.map(&mut |x| scalar_op(op,&[x]))?,
+        _ => return Err(CalcError::ShapeMismatch)
+    };
+    Ok(result.into_value())
+}
+
+pub fn eval_native(op: LinalgOp, args: &[Value]) -> Result<Value,CalcError> {
+    let args = args.iter().map(Operand::of).collect::<Result<Vec<_>,_>>()?;
+    let result = match (op,&args[..]) {
+        (LinalgOp::Dot,[Operand::Vector(a),Operand::Vector(b)]) if a.len() == b.len() => {
+            Operand::Scalar(a.iter().zip(b).map(|(a,b)| a * b).sum())
+        },
+        (LinalgOp::Cross,[Operand::Vector(a),Operand::Vector(b)]) if a.len() == 3 && b.len() == 3 => {
+            Operand::Vector(vec![a[1] * b[2] - a[2] * b[1],a[2] * b[0] - a[0] * b[2],a[0] * b[1] - a[1] * b[0]])
+        },
+        (LinalgOp::Transpose,[Operand::Matrix(m)]) => Operand::Matrix(m.transpose()),
+        (LinalgOp::Transpose,[Operand::Vector(v)]) => Operand::Matrix(Matrix::column(v)),
+        (LinalgOp::Det,[Operand::Matrix(m)]) => Operand::Scalar(m.det()?),
+        (LinalgOp::Inverse,[Operand::Matrix(m)]) => {
+            let mut inv = Matrix::identity(m.rows);
+            m.eliminate(&mut inv)?;
+            Operand::Matrix(inv)
+        },
+        (LinalgOp::Solve,[Operand::Matrix(a),Operand::Vector(b)]) if b.len() == a.rows => {
+            let mut x = Matrix::column(b);
+            a.eliminate(&mut x)?;
+            Operand::Vector(x.data)
+        },
+        (LinalgOp::Solve,[Operand::Matrix(a),Operand::Matrix(b)]) if b.rows == a.rows => {
+            let mut x = b.clone();
+            a.eliminate(&mut x)?;
+            Operand::Matrix(x)
+        },
+        _ => return Err(CalcError::ShapeMismatch)
+    };
+    Ok(result.into_value())
+}
@@ -0,0 +1,271 @@
+use super::value::Value;
+use super::ops::NumberOp;
+use super::backend::MAX_BIG_INTEGER_BITS;
+use super::CalcError;
+use num_bigint::BigInt;
+use num_traits::{Zero,One,Signed,ToPrimitive,FromPrimitive};
+
+/// Pollard rho steps tried before giving up on a factorization.
+const MAX_RHO_STEPS: u64 = 1_000_000;
+
+/// The exact integer `v` stands for.
+pub fn to_big_integer(v: &Value) -> Result<BigInt,CalcError> {
+    let not_integer = || CalcError::NotAnInteger(v.to_string());
+    match v {
+        Value::Integer(i) => Ok(BigInt::from(*i)),
+        Value::BigInt(n) => Ok(n.clone()),
+        Value::Float(f) if f.fract() == 0.0 => BigInt::from_f64(*f).ok_or_else(not_integer),
+        v => match v.to_rational() {
+            Some(r) if r.is_integer() => Ok(r.to_integer()),
+            _ => Err(not_integer())
+        }
+    }
+}
+
+fn natural(n: &BigInt) -> Result<u64,CalcError> {
+    if n.is_negative() {
+        return Err(CalcError::Negative(n.to_string()));
+    }
+    n.to_u64().ok_or(CalcError::TooLarge)
+}
+
+fn gcd(a: &BigInt, b: &BigInt) -> BigInt {
+    let (mut a,mut b) = (a.abs(),b.abs());
+    while !b.is_zero() {
+        let r = &a % &b;
+        a = std::mem::replace(&mut b,r);
+    }
+    a
+}
+
+/// Roughly how many bits n·(n−1)·…·(n−k+1) takes, for 0 ≤ k ≤ n.
+fn falling_bits(n: &BigInt, k: u64) -> f64 {
+    // Every factor is at most n.
+    let most = n.bits() as f64 * k as f64;
+    if most <= MAX_BIG_INTEGER_BITS as f64 {
+        return most;
+    }
+    let n = match n.to_f64() {
+        Some(n) if n.is_finite() => n,
+        _ => return f64::INFINITY
+    };
+    (ln_gamma(n + 1.0) - ln_gamma(n - k as f64 + 1.0)) / std::f64::consts::LN_2
+}
+
+/// n·(n−1)·…·(n−k+1), for 0 ≤ k ≤ n.
+fn falling(n: &BigInt, k: u64) -> Result<BigInt,CalcError> {
+    if falling_bits(n,k) > MAX_BIG_INTEGER_BITS as f64 {
+        return Err(CalcError::TooLarge);
+    }
+    Ok((0..k).fold(BigInt::one(),|acc,i| acc * (n - i)))
+}
+
+/// Miller-Rabin with the first twelve primes as bases, which is exact below
+/// 3.3·10²⁴ and almost certain above.
+pub fn is_prime(n: &BigInt) -> bool {
+    const BASES: [u32; 12] = [2,3,5,7,11,13,17,19,23,29,31,37];
+    if *n < BigInt::from(2) {
+        return false;
+    }
+    for b in BASES {
+        if *n == BigInt::from(b) {
+            return true;
+        }
+        if (n % b).is_zero() {
+            return false;
+        }
+    }
+    let one = BigInt::one();
+    let n1 = n - 1;
+    let s = n1.trailing_zeros().unwrap_or(0);
+    let d = &n1 >> s;
+    'bases: for b in BASES {
+        let mut x = BigInt::from(b).modpow(&d,n);
+        if x == one || x == n1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = x.modpow(&BigInt::from(2),n);
+            if x == n1 {
+                continue 'bases;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// A non-trivial divisor of the composite `n`.
+fn pollard_rho(n: &BigInt) -> Result<BigInt,CalcError> {
+    let mut steps = 0;
+    for c in 1u32.. {
+        let f = |x: &BigInt| (x * x + c) % n;
+        let (mut x,mut y) = (BigInt::from(2),BigInt::from(2));
+        loop {
+            steps += 1;
+            if steps > MAX_RHO_STEPS {
+                return Err(CalcError::TooLarge);
+            }
+            x = f(&x);
+            y = f(&f(&y));
+            let d = gcd(&(&x - &y),n);
+            if d == *n {
+                break;
+            }
+            if !d.is_one() {
+                return Ok(d);
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn factorize(n: BigInt, factors: &mut Vec<BigInt>) -> Result<(),CalcError> {
+    if n.is_one() {
+        return Ok(());
+    }
+    if is_prime(&n) {
+        factors.push(n);
+        return Ok(());
+    }
+    let d = pollard_rho(&n)?;
+    let rest = &n / &d;
+    factorize(d,factors)?;
+    factorize(rest,factors)
+}
+
+/// Prime factors of `n` with repetition, smallest first.
+fn factors(n: &BigInt) -> Result<Vec<BigInt>,CalcError> {
+    if n.is_negative() {
+        return Err(CalcError::Negative(n.to_string()));
+    }
+    if n.is_zero() {
+        return Err(CalcError::OutOfDomain);
+    }
+    let mut n = n.clone();
+    let mut factors = vec![];
+    for p in (2u32..1000).filter(|&p| (2..p).take_while(|q| q * q <= p).all(|q| p % q != 0)) {
+        while (&n % p).is_zero() {
+            n /= p;
+            factors.push(BigInt::from(p));
+        }
+    }
+    factorize(n,&mut factors)?;
+    factors.sort();
+    if factors.is_empty() {
+        factors.push(BigInt::one());
+    }
+    Ok(factors)
+}
+
+/// Coefficients of the Lanczos approximation, g = 7.
+const LANCZOS: [f64; 9] = [
+    0.99999999999980993, 676.5203681218851, -1259.1392167224028,
+    771.32342877765313, -176.61502916214059, 12.507343278686905,
+    -0.13857109526572012, 9.9843695780195716e-6, 1.5056327351493116e-7
+];
+
+/// The Lanczos series and t = x + 6.5 for Γ(x), x ≥ 0.5.
+fn lanczos(x: f64) -> (f64,f64) {
+    let x = x - 1.0;
+    let a = LANCZOS[1..].iter().enumerate().fold(LANCZOS[0],|a,(i,c)| a + c / (x + i as f64 + 1.0));
+    (a,x + 7.5)
+}
+
+/// Γ(x) by the Lanczos approximation.
+pub fn gamma(x: f64) -> f64 {
+    use std::f64::consts::PI;
+    if x < 0.5 {
+        return PI / ((PI * x).sin() * gamma(1.0 - x));
+    }
+    let (a,t) = lanczos(x);
+    (2.0 * PI).sqrt() * t.powf(x - 0.5) * (-t).exp() * a
+}
+
+/// ln Γ(x) for x ≥ 1, which stays finite long after Γ(x) overflows.
+fn ln_gamma(x: f64) -> f64 {
+    let (a,t) = lanczos(x);
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x - 0.5) * t.ln() - t + a.ln()
+}
+
+/// Runs `op` on integer arguments (X last) and returns the integers to push.
+pub fn eval(op: NumberOp, args: &[BigInt]) -> Result<Vec<BigInt>,CalcError> {
+    Ok(match (op,args) {
+        (NumberOp::Factorial,[n]) => vec![falling(n,natural(n)?)?],
+        (NumberOp::Gamma,[n]) if !n.is_positive() => return Err(CalcError::OutOfDomain),
+        (NumberOp::Gamma,[n]) => vec![falling(&(n - 1),natural(&(n - 1))?)?],
+        (NumberOp::Comb | NumberOp::Perm,[n,k]) => {
+            natural(n)?;
+            let k = natural(k)?;
+            if BigInt::from(k) > *n {
+                return Ok(vec![BigInt::zero()]);
+            }
+            match op {
+                NumberOp::Perm => vec![falling(n,k)?],
+                _ => {
+                    let k = k.min(natural(&(n - k))?);
+                    vec![falling(n,k)? / falling(&BigInt::from(k),k)?]
+                }
+            }
+        },
+        (NumberOp::Gcd,[a,b]) => vec![gcd(a,b)],
+        (NumberOp::Lcm,[a,b]) if a.is_zero() || b.is_zero() => vec![BigInt::zero()],
+        (NumberOp::Lcm,[a,b]) => vec![(a * b).abs() / gcd(a,b)],
+        (NumberOp::IsPrime,[n]) => vec![BigInt::from(is_prime(n) as u8)],
+        (NumberOp::Factor,[n]) => factors(n)?,
+        (NumberOp::ModPow,[b,e,m]) => {
+            if e.is_negative() {
+                return Err(CalcError::Negative(e.to_string()));
+            }
+            if !m.is_positive() {
+                return Err(CalcError::OutOfDomain);
+            }
+            let b = ((b % m) + m) % m;
+            vec![b.modpow(e,m)]
+        },
+        _ => unreachable!("arity checked by CalcUnit")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(op: NumberOp, args: &[i64]) -> Result<Vec<BigInt>,CalcError> {
+        eval(op,&args.iter().map(|&a| BigInt::from(a)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn small_factorials_are_exact() {
+        assert_eq!(run(NumberOp::Factorial,&[0]),Ok(vec![BigInt::one()]));
+        assert_eq!(run(NumberOp::Factorial,&[20]),Ok(vec![BigInt::from(2_432_902_008_176_640_000u64)]));
+        assert_eq!(run(NumberOp::Gamma,&[5]),Ok(vec![BigInt::from(24)]));
+        assert_eq!(run(NumberOp::Perm,&[10,3]),Ok(vec![BigInt::from(720)]));
+        assert_eq!(run(NumberOp::Comb,&[10,3]),Ok(vec![BigInt::from(120)]));
+    }
+
+    #[test]
+    fn factorials_are_capped_by_result_size() {
+        // 5000! takes about 54,000 bits, 10000! about 118,000.
+        let big = run(NumberOp::Factorial,&[5000]).unwrap().pop().unwrap();
+        assert!(big.bits() <= MAX_BIG_INTEGER_BITS);
+        assert_eq!(run(NumberOp::Factorial,&[10_000]),Err(CalcError::TooLarge));
+        assert_eq!(run(NumberOp::Factorial,&[100_000]),Err(CalcError::TooLarge));
+        assert_eq!(run(NumberOp::Gamma,&[10_001]),Err(CalcError::TooLarge));
+    }
+
+    #[test]
+    fn permutations_are_capped_by_result_size() {
+        // Few terms of a large n are cheap.
+        let perm = run(NumberOp::Perm,&[1_000_000_000,5]).unwrap().pop().unwrap();
+        assert_eq!(perm,(0..5).map(|i| BigInt::from(1_000_000_000 - i)).product::<BigInt>());
+        assert_eq!(run(NumberOp::Perm,&[100_000,10_000]),Err(CalcError::TooLarge));
+    }
+
+    #[test]
+    fn ln_gamma_estimates_bits() {
+        let exact = falling(&BigInt::from(3000),3000).unwrap().bits() as f64;
+        assert!((ln_gamma(3001.0) / std::f64::consts::LN_2 - exact).abs() < 1.0);
+        assert!((ln_gamma(0.5 + 1.0) - gamma(1.5).ln()).abs() < 1e-12);
+    }
+}
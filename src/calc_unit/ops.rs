@@ -0,0 +1,443 @@
+use ambassador::{delegatable_trait,Delegate};
+use super::units::UnitRef;
+
+#[delegatable_trait]
+pub trait ExtractCommand {
+    fn command_or_op(self) -> rpncalc::CommandOrOp;
+}
+
+/// How many stack elements an operation consumes.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Arity {
+    Fixed(usize),
+    Whole
+}
+
+impl std::fmt::Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Arity::Fixed(n) => write!(f,"{n}"),
+            Arity::Whole => write!(f,"all")
+        }
+    }
+}
+
+/// Everything `CalcUnit` can run. Commands and recalls rearrange the stack,
+/// ops are computed by the active backend, and native ops are the ones
+/// rpncalc has no counterpart for.
+#[derive(Clone,Copy,PartialEq)]
+pub enum CommOrOp {
+    Command(Command),
+    Op(Op),
+    Native(Native),
+    Recall(Recall)
+}
+
+impl CommOrOp {
+    pub fn arity(&self) -> Arity {
+        match self {
+            CommOrOp::Command(Command::Drop) |
+            CommOrOp::Command(Command::Dup)  => Arity::Fixed(1),
+            CommOrOp::Command(Command::Swap) => Arity::Fixed(2),
+            CommOrOp::Command(Command::Rev)  => Arity::Whole,
+            CommOrOp::Native(Native::Whole(_)) => Arity::Whole,
+            CommOrOp::Op(Op::Arith(_)) | CommOrOp::Op(Op::Exp2(_)) => Arity::Fixed(2),
+            CommOrOp::Op(Op::Exp(_))   | CommOrOp::Op(Op::Trig(_)) => Arity::Fixed(1),
+            CommOrOp::Op(Op::Constant(_)) | CommOrOp::Recall(_) => Arity::Fixed(0),
+            CommOrOp::Native(Native::Convert(_)) | CommOrOp::Native(Native::Complex(_)) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Hyperbolic(_)) | CommOrOp::Native(Native::Power(_)) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Part(Part::Round | Part::Mod | Part::IntDiv)) => Arity::Fixed(2),
+            CommOrOp::Native(Native::Part(_)) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Percent(_)) => Arity::Fixed(2),
+            CommOrOp::Native(Native::Tvm(TvmOp::Store(_))) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Tvm(_)) => Arity::Fixed(0),
+            CommOrOp::Native(Native::Formula(FormulaOp::Solve | FormulaOp::Derivative)) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Formula(FormulaOp::SolveBetween)) => Arity::Fixed(2),
+            CommOrOp::Native(Native::Formula(FormulaOp::Integrate)) => Arity::Fixed(3),
+            CommOrOp::Native(Native::NumberTheory(n)) => Arity::Fixed(match n {
+                NumberOp::ModPow => 3,
+                NumberOp::Comb | NumberOp::Perm | NumberOp::Gcd | NumberOp::Lcm => 2,
+                _ => 1
+            }),
+            CommOrOp::Native(Native::Bitwise(Bitwise::Not)) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Bitwise(_)) => Arity::Fixed(2),
+            CommOrOp::Native(Native::Units(UnitOp::Convert)) => Arity::Fixed(2),
+            CommOrOp::Native(Native::Units(_)) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Linalg(LinalgOp::Dot | LinalgOp::Cross | LinalgOp::Solve)) => Arity::Fixed(2),
+            CommOrOp::Native(Native::Linalg(_)) => Arity::Fixed(1),
+            CommOrOp::Native(Native::Stats(s)) => Arity::Fixed(match s {
+                StatOp::AddPair | StatOp::RemovePair => 2,
+                StatOp::Add | StatOp::Remove | StatOp::ForecastY | StatOp::ForecastX => 1,
+                _ => 0
+            })
+        }
+    }
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Native {
+    Convert(Convert),
+    Complex(ComplexOp),
+    Hyperbolic(Hyperbolic),
+    Power(Power),
+    Part(Part),
+    NumberTheory(NumberOp),
+    Percent(PercentOp),
+    Bitwise(Bitwise),
+    Units(UnitOp),
+    Linalg(LinalgOp),
+    /// Served by `CalcUnit`, which holds the Σ registers.
+    Stats(StatOp),
+    /// Served by `CalcUnit`, which holds the TVM registers.
+    Tvm(TvmOp),
+    /// Served by `CalcUnit`, which holds the recorded formula.
+    Formula(FormulaOp),
+    Whole(WholeOp)
+}
+
+/// Reductions and rearrangements of the whole stack, or of its top N levels
+/// when a count is given.
+#[derive(Clone,Copy,PartialEq)]
+pub enum WholeOp {
+    Sum,
+    Product,
+    Mean,
+    Median,
+    /// Sorts so that the largest value ends up in X.
+    SortAsc,
+    /// Sorts so that the smallest value ends up in X.
+    SortDesc,
+    Min,
+    Max,
+    /// Drops repeated values, keeping the one nearest the bottom.
+    Unique
+}
+
+/// Statistics over the points accumulated with Σ+. Summaries of x are pushed
+/// to X, with the same summary of y in Y when pairs were entered.
+#[derive(Clone,Copy,PartialEq)]
+pub enum StatOp {
+    /// Σ+: accumulates X as a lone x.
+    Add,
+    /// Σ+ for pairs: X is x and Y is y.
+    AddPair,
+    Remove,
+    RemovePair,
+    Count,
+    Mean,
+    /// Sample standard deviation.
+    SDev,
+    /// Population standard deviation.
+    PSDev,
+    Slope,
+    Intercept,
+    /// Correlation coefficient r.
+    Corr,
+    /// ŷ: the regression line at x = X.
+    ForecastY,
+    /// x̂: where the regression line reaches y = X.
+    ForecastX,
+    Clear
+}
+
+/// The time-value-of-money registers.
+#[derive(Clone,Copy,PartialEq)]
+pub enum TvmVar {
+    /// Number of payments.
+    N,
+    /// Nominal annual interest rate, in percent.
+    IYr,
+    Pv,
+    Pmt,
+    Fv,
+    /// Payments per year.
+    PYr
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum TvmOp {
+    /// Copies X into a register, leaving it on the stack.
+    Store(TvmVar),
+    /// Works out a register from the others and pushes it.
+    Solve(TvmVar),
+    /// Switches payments between the start and the end of each period.
+    ToggleBegin,
+    /// Zeroes N, I/YR, PV, PMT and FV.
+    Clear
+}
+
+/// Numerical methods on the recorded formula f(x).
+#[derive(Clone,Copy,PartialEq)]
+pub enum FormulaOp {
+    /// A root of f near the guess X.
+    Solve,
+    /// A root of f between Y and X, or near them when f has the same sign
+    /// at both.
+    SolveBetween,
+    /// ∫ f from Z to Y, to within X. The error estimate goes to Y.
+    Integrate,
+    /// f′ at X. The error estimate goes to Y.
+    Derivative
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum LinalgOp {
+    Dot,
+    Cross,
+    Transpose,
+    Det,
+    Inverse,
+    /// Solves A·x = b for x, with A in Y and b in X.
+    Solve
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum UnitOp {
+    /// Gives a plain X the unit, or converts X into it.
+    To(UnitRef),
+    /// Converts Y into the unit of X, whose magnitude is ignored.
+    Convert,
+    /// Drops X's unit, keeping the magnitude.
+    Strip
+}
+
+/// Operations on the bits of integer words. Shifts and rotations move Y by
+/// X places.
+#[derive(Clone,Copy,PartialEq)]
+pub enum Bitwise {
+    And,
+    Or,
+    Xor,
+    Not,
+    Shl,
+    /// Arithmetic for signed words, logical for unsigned ones.
+    Shr,
+    RotL,
+    RotR
+}
+
+/// Parts of a complex number. Real values count as complex numbers with no
+/// imaginary part.
+#[derive(Clone,Copy,PartialEq)]
+pub enum ComplexOp {
+    Conj,
+    Re,
+    Im,
+    Abs,
+    Arg
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Hyperbolic {
+    Sinh,
+    Cosh,
+    Tanh,
+    ASinh,
+    ACosh,
+    ATanh
+}
+
+/// Powers of a fixed base, raised to X.
+#[derive(Clone,Copy,PartialEq)]
+pub enum Power {
+    E,
+    Ten,
+    Two
+}
+
+/// Rounding, integer and fractional parts, and floored division. Exact
+/// values stay exact.
+#[derive(Clone,Copy,PartialEq)]
+pub enum Part {
+    Floor,
+    Ceil,
+    /// Rounds Y to X decimal places; negative X rounds to tens, hundreds...
+    Round,
+    /// Integer part, rounding towards zero.
+    Trunc,
+    /// Fractional part, with the sign of the value.
+    Frac,
+    Sign,
+    /// Remainder of Y / X, with the sign of X.
+    Mod,
+    /// Y / X rounded down.
+    IntDiv
+}
+
+/// Combinatorics and number theory on exact integers, with results as large
+/// as they need to be.
+#[derive(Clone,Copy,PartialEq)]
+pub enum NumberOp {
+    /// X!, or Γ(X + 1) when X is not an integer.
+    Factorial,
+    Gamma,
+    /// Combinations of X items out of Y.
+    Comb,
+    /// Permutations of X items out of Y.
+    Perm,
+    Gcd,
+    Lcm,
+    /// 1 when X is prime, 0 otherwise.
+    IsPrime,
+    /// Replaces X with its prime factors, smallest deepest.
+    Factor,
+    /// Z to the power of Y, modulo X.
+    ModPow
+}
+
+/// Business calculator percentages. Each keeps the base value in Y and
+/// puts its result in X.
+#[derive(Clone,Copy,PartialEq)]
+pub enum PercentOp {
+    /// X percent of Y.
+    Percent,
+    /// Change from Y to X, in percent of Y.
+    Change,
+    /// X as a percentage of the total Y.
+    OfTotal,
+    /// Price from cost Y with a markup of X percent on cost.
+    Markup,
+    /// Price from cost Y with a margin of X percent of price.
+    Margin,
+    /// Margin of price X over cost Y, in percent of price.
+    MarginOf
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Convert {
+    /// Turns X into its best rational approximation, or a fraction back
+    /// into the backend's inexact type.
+    ToggleFraction
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Recall {
+    /// Push back X as it was before the last op consumed it.
+    LastX,
+    /// Push back every argument the last op consumed.
+    LastArgs
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Command {
+    Drop,
+    Dup,
+    Swap,
+    Rev
+}
+
+impl ExtractCommand for Command {
+    fn command_or_op(self) -> rpncalc::CommandOrOp {
+        rpncalc::CommandOrOp::Command(match self {
+            Command::Drop => rpncalc::CommandEnum::Drop(rpncalc::Drop::Some(1)),
+            Command::Dup  => rpncalc::CommandEnum::Dup(rpncalc::Dup(1)),
+            Command::Swap => rpncalc::CommandEnum::Swap(rpncalc::Swap::LastTwo),
+            Command::Rev  => rpncalc::CommandEnum::Reverse(rpncalc::Reverse)
+        })
+    }
+}
+
+#[derive(Clone,Copy,PartialEq,Delegate)]
+#[delegate(ExtractCommand)]
+pub enum Op {
+    Arith(Arith),
+    Constant(Constant),
+    Exp2(Exp2),
+    Exp(Exp),
+    Trig(Trig)
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Constant {
+    Pi,
+    E,
+    Inf
+}
+
+impl ExtractCommand for Constant {
+    fn command_or_op(self) -> rpncalc::CommandOrOp {
+        match self {
+            Constant::E   => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Constants(rpncalc::ops::Constants::E  )),
+            Constant::Pi  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Constants(rpncalc::ops::Constants::PI )),
+            Constant::Inf => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Constants(rpncalc::ops::Constants::Inf)),
+        }
+    }
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Arith {
+    Add,
+    Sub,
+    Mul,
+    Div
+}
+
+impl ExtractCommand for Arith {
+    fn command_or_op(self) -> rpncalc::CommandOrOp {
+        rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Arith(match self {
+            Arith::Add => rpncalc::ops::Arith::Add,
+            Arith::Sub => rpncalc::ops::Arith::Sub,
+            Arith::Mul => rpncalc::ops::Arith::Mul,
+            Arith::Div => rpncalc::ops::Arith::Div
+        }))
+    }
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Exp2 {
+    Pow,
+    LogN,
+    RootN
+}
+
+impl ExtractCommand for Exp2 {
+    fn command_or_op(self) -> rpncalc::CommandOrOp {
+        match self {
+            Exp2::Pow => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsBinary(rpncalc::ops::ExponentialsBinary::Pow)),
+            Exp2::LogN => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsBinary(rpncalc::ops::ExponentialsBinary::LogN)),
+            Exp2::RootN => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsBinary(rpncalc::ops::ExponentialsBinary::RootN))
+        }
+    }
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Exp {
+    Log10,
+    Log2,
+    LogE,
+    Root2
+}
+
+impl ExtractCommand for Exp {
+    fn command_or_op(self) -> rpncalc::CommandOrOp {
+        match self {
+            Exp::Log10 => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::Log10)),
+            Exp::Log2  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::Log2 )),
+            Exp::LogE  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::LogE )),
+            Exp::Root2 => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::ExponentialsUnary(rpncalc::ops::ExponentialsUnary::Root2))
+        }
+    }
+}
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Trig {
+    Sin,
+    Cos,
+    Tan,
+    ASin,
+    ACos,
+    ATan
+}
+
+impl ExtractCommand for Trig {
+    fn command_or_op(self) -> rpncalc::CommandOrOp {
+        match self {
+            Trig::Sin  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::Sin )),
+            Trig::Cos  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::Cos )),
+            Trig::Tan  => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::Tan )),
+            Trig::ASin => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::ASin)),
+            Trig::ACos => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::ACos)),
+            Trig::ATan => rpncalc::CommandOrOp::Op(rpncalc::ops::OpEnum::Trigonometric(rpncalc::ops::Trigonometric::ATan))
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use super::ops::StatOp;
+use super::CalcError;
+
+/// One accumulated sample; `y` is set for points entered as pairs.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct Point {
+    pub x: f64,
+    pub y: Option<f64>
+}
+
+/// The Σ registers, kept as the list of points so entries can be corrected
+/// after the fact.
+#[derive(Clone,PartialEq,Default)]
+pub struct Stats {
+    pub points: Vec<Point>
+}
+
+struct Regression {
+    slope: f64,
+    intercept: f64,
+    r: f64
+}
+
+fn mean(v: &[f64]) -> Option<f64> {
+    match v.len() {
+        0 => None,
+        n => Some(v.iter().sum::<f64>() / n as f64)
+    }
+}
+
+/// Sample (n−1) or population (n) standard deviation.
+fn sdev(v: &[f64], sample: bool) -> Option<f64> {
+    let m = mean(v)?;
+    let n = v.len() - sample as usize;
+    if n == 0 {
+        return None;
+    }
+    Some((v.iter().map(|x| (x - m).powi(2)).sum::<f64>() / n as f64).sqrt())
+}
+
+impl Stats {
+    fn xs(&self) -> Vec<f64> {
+        self.points.iter().map(|p| p.x).collect()
+    }
+    fn pairs(&self) -> Vec<(f64,f64)> {
+        self.points.iter().filter_map(|p| Some((p.x,p.y?))).collect()
+    }
+    fn fit(&self) -> Result<Regression,CalcError> {
+        self.regression().ok_or(CalcError::NotEnoughData)
+    }
+    /// Least-squares line through the pairs.
+    fn regression(&self) -> Option<Regression> {
+        let pairs = self.pairs();
+        let n = pairs.len() as f64;
+        if pairs.len() < 2 {
+            return None;
+        }
+        let (mx,my) = (pairs.iter().map(|p| p.0).sum::<f64>() / n,pairs.iter().map(|p| p.1).sum::<f64>() / n);
+        let sxx = pairs.iter().map(|p| (p.0 - mx).powi(2)).sum::<f64>();
+        let syy = pairs.iter().map(|p| (p.1 - my).powi(2)).sum::<f64>();
+        let sxy = pairs.iter().map(|p| (p.0 - mx) * (p.1 - my)).sum::<f64>();
+        if sxx == 0.0 {
+            return None;
+        }
+        let slope = sxy / sxx;
+        Some(Regression { slope, intercept: my - slope * mx, r: sxy / (sxx * syy).sqrt() })
+    }
+    /// A summary of x, preceded by the same summary of y when there are pairs.
+    fn with_y(&self, f: impl Fn(&[f64]) -> Option<f64>) -> Result<Vec<f64>,CalcError> {
+        let x = f(&self.xs()).ok_or(CalcError::NotEnoughData)?;
+        let ys = self.pairs().into_iter().map(|p| p.1).collect::<Vec<_>>();
+        Ok(match ys.is_empty() {
+            true => vec![x],
+            false => vec![f(&ys).ok_or(CalcError::NotEnoughData)?,x]
+        })
+    }
+    fn remove(&mut self, point: Point) -> Result<(),CalcError> {
+        let i = self.points.iter().rposition(|p| *p == point).ok_or(CalcError::NoSuchPoint)?;
+        self.points.remove(i);
+        Ok(())
+    }
+    /// Runs `op` on `args` (X last) and returns the values to push back.
+    pub fn eval(&mut self, op: StatOp, args: &[f64]) -> Result<Vec<f64>,CalcError> {
+        Ok(match (op,args) {
+            (StatOp::Add,&[x]) => {
+                self.points.push(Point { x, y: None });
+                vec![self.points.len() as f64]
+            },
+            (StatOp::AddPair,&[y,x]) => {
+                self.points.push(Point { x, y: Some(y) });
+                vec![self.points.len() as f64]
+            },
+            (StatOp::Remove,&[x]) => {
+                self.remove(Point { x, y: None })?;
+                vec![self.points.len() as f64]
+            },
+            (StatOp::RemovePair,&[y,x]) => {
+                self.remove(Point { x, y: Some(y) })?;
+                vec![self.points.len() as f64]
+            },
+            (StatOp::Count,_) => vec![self.points.len() as f64],
+            (StatOp::Mean,_) => self.with_y(mean)?,
+            (StatOp::SDev,_) => self.with_y(|v| sdev(v,true))?,
+            (StatOp::PSDev,_) => self.with_y(|v| sdev(v,false))?,
+            (StatOp::Slope,_) => vec![self.fit()?.slope],
+            (StatOp::Intercept,_) => vec![self.fit()?.intercept],
+            (StatOp::Corr,_) => match self.fit()?.r {
+                // Every y the same leaves nothing to correlate with.
+                r if r.is_nan() => return Err(CalcError::OutOfDomain),
+                r => vec![r]
+            },
+            (StatOp::ForecastY,&[x]) => {
+                let fit = self.fit()?;
+                vec![fit.intercept + fit.slope * x]
+            },
+            (StatOp::ForecastX,&[y]) => {
+                let fit = self.fit()?;
+                if fit.slope == 0.0 {
+                    return Err(CalcError::DivisionByZero);
+                }
+                vec![(y - fit.intercept) / fit.slope]
+            },
+            (StatOp::Clear,_) => {
+                self.points.clear();
+                vec![]
+            },
+            _ => unreachable!("arity checked by CalcUnit")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    fn with_pairs(pairs: &[(f64,f64)]) -> Stats {
+        let mut stats = Stats::default();
+        for &(x,y) in pairs {
+            stats.eval(StatOp::AddPair,&[y,x]).unwrap();
+        }
+        stats
+    }
+
+    #[test]
+    fn mean_and_deviations() {
+        let mut stats = Stats::default();
+        for x in [2.0,4.0,4.0,4.0,5.0,5.0,7.0,9.0] {
+            stats.eval(StatOp::Add,&[x]).unwrap();
+        }
+        assert_eq!(stats.eval(StatOp::Count,&[]),Ok(vec![8.0]));
+        assert_eq!(stats.eval(StatOp::Mean,&[]),Ok(vec![5.0]));
+        assert_eq!(stats.eval(StatOp::PSDev,&[]),Ok(vec![2.0]));
+        let s = stats.eval(StatOp::SDev,&[]).unwrap();
+        assert!(close(s[0],(32.0f64 / 7.0).sqrt()),"{s:?}");
+    }
+
+    #[test]
+    fn pairs_summarize_y_below_x() {
+        let mut stats = with_pairs(&[(1.0,10.0),(3.0,20.0)]);
+        assert_eq!(stats.eval(StatOp::Mean,&[]),Ok(vec![15.0,2.0]));
+    }
+
+    #[test]
+    fn too_little_data() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.eval(StatOp::Mean,&[]),Err(CalcError::NotEnoughData));
+        stats.eval(StatOp::Add,&[1.0]).unwrap();
+        assert_eq!(stats.eval(StatOp::SDev,&[]),Err(CalcError::NotEnoughData));
+        assert_eq!(stats.eval(StatOp::Slope,&[]),Err(CalcError::NotEnoughData));
+    }
+
+    #[test]
+    fn regression_and_forecasts() {
+        // y = 2x + 1, off by ±0.5 in a way least squares averages out.
+        let mut stats = with_pairs(&[(0.0,1.5),(1.0,2.5),(2.0,4.5),(3.0,7.5)]);
+        assert!(close(stats.eval(StatOp::Slope,&[]).unwrap()[0],2.0));
+        assert!(close(stats.eval(StatOp::Intercept,&[]).unwrap()[0],1.0));
+        assert!(close(stats.eval(StatOp::ForecastY,&[10.0]).unwrap()[0],21.0));
+        assert!(close(stats.eval(StatOp::ForecastX,&[21.0]).unwrap()[0],10.0));
+        let r = stats.eval(StatOp::Corr,&[]).unwrap()[0];
+        assert!(r > 0.9 && r < 1.0,"{r}");
+        let mut exact = with_pairs(&[(1.0,3.0),(2.0,1.0),(3.0,-1.0)]);
+        assert!(close(exact.eval(StatOp::Corr,&[]).unwrap()[0],-1.0));
+    }
+
+    #[test]
+    fn flat_data_has_no_correlation() {
+        let mut stats = with_pairs(&[(1.0,4.0),(2.0,4.0),(3.0,4.0)]);
+        assert_eq!(stats.eval(StatOp::Slope,&[]),Ok(vec![0.0]));
+        assert_eq!(stats.eval(StatOp::Corr,&[]),Err(CalcError::OutOfDomain));
+        assert_eq!(stats.eval(StatOp::ForecastX,&[4.0]),Err(CalcError::DivisionByZero));
+        let mut vertical = with_pairs(&[(2.0,1.0),(2.0,5.0)]);
+        assert_eq!(vertical.eval(StatOp::Slope,&[]),Err(CalcError::NotEnoughData));
+    }
+
+    #[test]
+    fn removing_takes_out_the_latest_match() {
+        let mut stats = Stats::default();
+        for x in [1.0,2.0,1.0] {
+            stats.eval(StatOp::Add,&[x]).unwrap();
+        }
+        stats.eval(StatOp::AddPair,&[5.0,1.0]).unwrap();
+        assert_eq!(stats.eval(StatOp::Remove,&[1.0]),Ok(vec![3.0]));
+        assert_eq!(stats.points,vec![Point { x: 1.0, y: None },Point { x: 2.0, y: None },Point { x: 1.0, y: Some(5.0) }]);
+        assert_eq!(stats.eval(StatOp::RemovePair,&[5.0,1.0]),Ok(vec![2.0]));
+        assert_eq!(stats.eval(StatOp::RemovePair,&[5.0,1.0]),Err(CalcError::NoSuchPoint));
+        assert_eq!(stats.eval(StatOp::Remove,&[7.0]),Err(CalcError::NoSuchPoint));
+        assert_eq!(stats.eval(StatOp::Mean,&[]),Ok(vec![1.5]));
+    }
+}
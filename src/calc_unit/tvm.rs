@@ -0,0 +1,229 @@
+use super::ops::{TvmOp,TvmVar};
+use super::CalcError;
+
+/// Longest amortization schedule worked out, in payments.
+const MAX_SCHEDULE: usize = 1200;
+
+/// The time-value-of-money registers. Money received is positive and money
+/// paid out negative, as on financial calculators.
+#[derive(Clone,PartialEq)]
+pub struct Tvm {
+    pub n: f64,
+    /// Nominal annual interest rate, in percent.
+    pub i_yr: f64,
+    pub pv: f64,
+    pub pmt: f64,
+    pub fv: f64,
+    /// Payments per year.
+    pub p_yr: f64,
+    /// Whether payments fall at the start of each period rather than the end.
+    pub begin: bool
+}
+
+impl Default for Tvm {
+    fn default() -> Self {
+        Tvm { n: 0.0, i_yr: 0.0, pv: 0.0, pmt: 0.0, fv: 0.0, p_yr: 12.0, begin: false }
+    }
+}
+
+/// One payment of an amortization schedule. Interest and principal carry
+/// the sign of the payment.
+pub struct AmortRow {
+    pub period: usize,
+    pub interest: f64,
+    pub principal: f64,
+    /// What is left to pay after this payment.
+    pub balance: f64
+}
+
+impl Tvm {
+    /// Interest rate per period, as a fraction.
+    fn rate(&self) -> f64 {
+        self.i_yr / 100.0 / self.p_yr
+    }
+    /// PV + PMT·(1 + i·b)·(1 − (1 + i)⁻ᴺ)/i + FV·(1 + i)⁻ᴺ, which is zero when
+    /// the registers balance at periodic rate `i`.
+    fn imbalance(&self, i: f64) -> f64 {
+        if i == 0.0 {
+            return self.pv + self.pmt * self.n + self.fv;
+        }
+        let v = (1.0 + i).powf(-self.n);
+        let b = if self.begin { 1.0 + i } else { 1.0 };
+        self.pv + self.pmt * b * (1.0 - v) / i + self.fv * v
+    }
+    /// Newton's method on the periodic rate, with a numerical derivative.
+    fn solve_rate(&self) -> Result<f64,CalcError> {
+        let mut i = 0.01;
+        for _ in 0..100 {
+            let f = self.imbalance(i);
+            let h = 1e-7 * (1.0 + i.abs());
+            let d = (self.imbalance(i + h) - f) / h;
+            if d == 0.0 || !d.is_finite() {
+                break;
+            }
+            let mut next = i - f / d;
+            // Rates at or below -100% have no meaning.
+            if next <= -1.0 {
+                next = (i - 1.0) / 2.0;
+            }
+            if (next - i).abs() < 1e-12 {
+                return Ok(next);
+            }
+            i = next;
+        }
+        Err(CalcError::NoSolution)
+    }
+    fn solve(&self, var: TvmVar) -> Result<f64,CalcError> {
+        let i = self.rate();
+        let v = (1.0 + i).powf(-self.n);
+        let b = if self.begin { 1.0 + i } else { 1.0 };
+        // What a payment of one per period is worth today.
+        let annuity = if i == 0.0 { self.n } else { b * (1.0 - v) / i };
+        let result = match var {
+            TvmVar::Pv => -(self.pmt * annuity + self.fv * v),
+            TvmVar::Pmt => -(self.pv + self.fv * v) / annuity,
+            TvmVar::Fv => -(self.pv + self.pmt * annuity) / v,
+            TvmVar::N if i == 0.0 => -(self.pv + self.fv) / self.pmt,
+            TvmVar::N => {
+                // PV + k·(1 − v) + FV·v = 0, solved for v = (1 + i)⁻ᴺ.
+                let k = self.pmt * b / i;
+                let v = (self.pv + k) / (k - self.fv);
+                -v.ln() / (1.0 + i).ln()
+            },
+            TvmVar::IYr => self.solve_rate()? * self.p_yr * 100.0,
+            TvmVar::PYr => f64::NAN
+        };
+        match result.is_finite() {
+            true => Ok(result),
+            false => Err(CalcError::NoSolution)
+        }
+    }
+    fn register(&mut self, var: TvmVar) -> &mut f64 {
+        match var {
+            TvmVar::N => &mut self.n,
+            TvmVar::IYr => &mut self.i_yr,
+            TvmVar::Pv => &mut self.pv,
+            TvmVar::Pmt => &mut self.pmt,
+            TvmVar::Fv => &mut self.fv,
+            TvmVar::PYr => &mut self.p_yr
+        }
+    }
+    /// How each payment splits into interest and principal, for the first N
+    /// payments.
+    pub fn schedule(&self) -> Vec<AmortRow> {
+        let i = self.rate();
+        let periods = self.n.round().clamp(0.0,MAX_SCHEDULE as f64) as usize;
+        let mut balance = self.pv;
+        (1..=periods).map(|period| {
+            let interest = if self.begin && period == 1 { 0.0 } else { -balance * i };
+            let principal = self.pmt - interest;
+            balance += principal;
+            AmortRow { period, interest, principal, balance }
+        }).collect()
+    }
+    /// Runs `op` on `args` (X last) and returns the values to push back.
+    /// `CalcUnit` puts a stored X back itself.
+    pub fn eval(&mut self, op: TvmOp, args: &[f64]) -> Result<Vec<f64>,CalcError> {
+        Ok(match (op,args) {
+            (TvmOp::Store(TvmVar::PYr),&[x]) if x <= 0.0 || x.fract() != 0.0 => return Err(CalcError::OutOfDomain),
+            (TvmOp::Store(var),&[x]) => {
+                *self.register(var) = x;
+                vec![]
+            },
+            (TvmOp::Solve(var),_) => {
+                let x = self.solve(var)?;
+                *self.register(var) = x;
+                vec![x]
+            },
+            (TvmOp::ToggleBegin,_) => {
+                self.begin = !self.begin;
+                vec![]
+            },
+            (TvmOp::Clear,_) => {
+                *self = Tvm { p_yr: self.p_yr, begin: self.begin, ..Tvm::default() };
+                vec![]
+            },
+            _ => unreachable!("arity checked by CalcUnit")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 30-year, $200,000 mortgage at 6% a year, paid monthly.
+    fn mortgage() -> Tvm {
+        Tvm { n: 360.0, i_yr: 6.0, pv: 200_000.0, ..Tvm::default() }
+    }
+
+    fn close(a: f64, b: f64, tol: f64) -> bool {
+        (a - b).abs() <= tol
+    }
+
+    #[test]
+    fn mortgage_payment() {
+        let pmt = mortgage().solve(TvmVar::Pmt).unwrap();
+        assert!(close(pmt,-1199.10,0.005),"{pmt}");
+    }
+
+    #[test]
+    fn mortgage_term_and_principal() {
+        let tvm = Tvm { pmt: -1199.101050305514, ..mortgage() };
+        assert!(close(tvm.solve(TvmVar::N).unwrap(),360.0,1e-6));
+        assert!(close(tvm.solve(TvmVar::Pv).unwrap(),200_000.0,1e-6));
+        assert!(close(tvm.solve(TvmVar::Fv).unwrap(),0.0,1e-4));
+    }
+
+    #[test]
+    fn newton_finds_the_mortgage_rate() {
+        let tvm = Tvm { i_yr: 0.0, pmt: -1199.101050305514, ..mortgage() };
+        let i_yr = tvm.solve(TvmVar::IYr).unwrap();
+        assert!(close(i_yr,6.0,1e-8),"{i_yr}");
+    }
+
+    #[test]
+    fn begin_mode_annuity() {
+        // $100 saved at the start of each of 10 years at 5%.
+        let tvm = Tvm { n: 10.0, i_yr: 5.0, pmt: -100.0, p_yr: 1.0, begin: true, ..Tvm::default() };
+        assert!(close(tvm.solve(TvmVar::Fv).unwrap(),1320.68,0.005));
+        assert!(close(tvm.solve(TvmVar::Pv).unwrap(),810.78,0.005));
+        let end = Tvm { begin: false, ..tvm };
+        assert!(close(end.solve(TvmVar::Fv).unwrap(),1257.79,0.005));
+    }
+
+    #[test]
+    fn zero_rate_is_plain_arithmetic() {
+        let tvm = Tvm { n: 12.0, pv: 1200.0, ..Tvm::default() };
+        assert_eq!(tvm.solve(TvmVar::Pmt).unwrap(),-100.0);
+    }
+
+    #[test]
+    fn unbalanced_registers_have_no_rate() {
+        // Paying back less than was borrowed, at any positive rate.
+        let tvm = Tvm { n: 10.0, pv: 1000.0, pmt: 10.0, ..Tvm::default() };
+        assert_eq!(tvm.solve(TvmVar::IYr),Err(CalcError::NoSolution));
+    }
+
+    #[test]
+    fn schedule_pays_off_the_mortgage() {
+        let tvm = Tvm { pmt: mortgage().solve(TvmVar::Pmt).unwrap(), ..mortgage() };
+        let rows = tvm.schedule();
+        assert_eq!(rows.len(),360);
+        assert!(close(rows[0].interest,-1000.0,1e-9));
+        assert!(close(rows[0].principal,-199.10,0.005));
+        assert!(close(rows[0].balance,199_800.90,0.005));
+        assert!(close(rows[359].balance,0.0,1e-6));
+        let interest = rows.iter().map(|r| r.interest).sum::<f64>();
+        assert!(close(interest,-231_676.38,0.01),"{interest}");
+    }
+
+    #[test]
+    fn begin_mode_schedule_has_no_first_interest() {
+        let tvm = Tvm { n: 3.0, i_yr: 12.0, pv: 1000.0, pmt: -300.0, p_yr: 12.0, begin: true, ..Tvm::default() };
+        let rows = tvm.schedule();
+        assert_eq!(rows[0].interest,0.0);
+        assert_eq!(rows[0].principal,-300.0);
+        assert!(close(rows[1].interest,-7.0,1e-9));
+    }
+}
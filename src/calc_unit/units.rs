@@ -0,0 +1,291 @@
+use super::value::Value;
+use super::ops::{Op,Arith,Exp2,Exp,UnitOp};
+use super::CalcError;
+
+/// Exponents of the SI base dimensions, in the order of `BASE_SYMBOLS`.
+#[derive(Clone,Copy,PartialEq,Debug,Default)]
+pub struct Dims(pub [i8; 7]);
+
+const BASE_SYMBOLS: [&str; 7] = ["kg","m","s","A","K","mol","cd"];
+
+impl Dims {
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&e| e == 0)
+    }
+    /// Combines the exponents pairwise; `f` gives `None` when one overflows.
+    fn zip(self, other: Dims, f: impl Fn(i8,i8) -> Option<i8>) -> Result<Dims,CalcError> {
+        let mut out = self;
+        for (o,e) in out.0.iter_mut().zip(other.0) {
+            *o = f(*o,e).ok_or(CalcError::TooLarge)?;
+        }
+        Ok(out)
+    }
+    fn scale(self, n: i8) -> Result<Dims,CalcError> {
+        self.zip(self,|e,_| e.checked_mul(n))
+    }
+}
+
+/// A unit a quantity is expressed in: its dimensions, how many SI units one
+/// of it is, and how it is written.
+#[derive(Clone,PartialEq,Debug)]
+pub struct Unit {
+    pub dims: Dims,
+    pub factor: f64,
+    pub symbol: String
+}
+
+impl Unit {
+    fn one() -> Unit {
+        Unit { dims: Dims::default(), factor: 1.0, symbol: String::new() }
+    }
+    /// The coherent SI unit of `dims`, written in base units, e.g. `kg·m/s²`.
+    fn si(dims: Dims) -> Unit {
+        let part = |(sym,&e): (&&str,&i8)| match e.abs() {
+            1 => sym.to_string(),
+            n => format!("{sym}{}",superscript(n))
+        };
+        let num = BASE_SYMBOLS.iter().zip(&dims.0).filter(|(_,e)| **e > 0).map(part).collect::<Vec<_>>();
+        let den = BASE_SYMBOLS.iter().zip(&dims.0).filter(|(_,e)| **e < 0).map(part).collect::<Vec<_>>();
+        let num = if num.is_empty() { "1".to_string() } else { num.join("·") };
+        let symbol = match den.len() {
+            0 => num,
+            1 => format!("{num}/{}",den[0]),
+            _ => format!("{num}/({})",den.join("·"))
+        };
+        Unit { dims, factor: 1.0, symbol }
+    }
+}
+
+fn superscript(n: i8) -> String {
+    n.to_string().chars().map(|c| match c {
+        '0' => '⁰', '1' => '¹', '2' => '²', '3' => '³', '4' => '⁴',
+        '5' => '⁵', '6' => '⁶', '7' => '⁷', '8' => '⁸', '9' => '⁹',
+        _ => c
+    }).collect()
+}
+
+pub struct UnitDef {
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub dims: Dims,
+    pub factor: f64,
+    /// Whether SI prefixes make sense on this unit.
+    pub prefixable: bool
+}
+
+pub struct Prefix {
+    pub symbol: &'static str,
+    pub factor: f64
+}
+
+macro_rules! unit {
+    ($sym:literal, $name:literal, [$($d:literal),*], $factor:expr, $prefixable:literal) => {
+        UnitDef { symbol: $sym, name: $name, dims: Dims([$($d),*]), factor: $factor, prefixable: $prefixable }
+    };
+}
+
+/// Units offered by the unit picker. Dimensions are kg, m, s, A, K, mol, cd.
+pub static UNITS: &[UnitDef] = &[
+    unit!("m"  ,"metre"           ,[0,1,0,0,0,0,0]  ,1.0            ,true ),
+    unit!("g"  ,"gram"            ,[1,0,0,0,0,0,0]  ,1e-3           ,true ),
+    unit!("s"  ,"second"          ,[0,0,1,0,0,0,0]  ,1.0            ,true ),
+    unit!("A"  ,"ampere"          ,[0,0,0,1,0,0,0]  ,1.0            ,true ),
+    unit!("K"  ,"kelvin"          ,[0,0,0,0,1,0,0]  ,1.0            ,true ),
+    unit!("mol","mole"            ,[0,0,0,0,0,1,0]  ,1.0            ,true ),
+    unit!("cd" ,"candela"         ,[0,0,0,0,0,0,1]  ,1.0            ,true ),
+    unit!("N"  ,"newton"          ,[1,1,-2,0,0,0,0] ,1.0            ,true ),
+    unit!("J"  ,"joule"           ,[1,2,-2,0,0,0,0] ,1.0            ,true ),
+    unit!("W"  ,"watt"            ,[1,2,-3,0,0,0,0] ,1.0            ,true ),
+    unit!("Pa" ,"pascal"          ,[1,-1,-2,0,0,0,0],1.0            ,true ),
+    unit!("Hz" ,"hertz"           ,[0,0,-1,0,0,0,0] ,1.0            ,true ),
+    unit!("C"  ,"coulomb"         ,[0,0,1,1,0,0,0]  ,1.0            ,true ),
+    unit!("V"  ,"volt"            ,[1,2,-3,-1,0,0,0],1.0            ,true ),
+    unit!("Ω"  ,"ohm"             ,[1,2,-3,-2,0,0,0],1.0            ,true ),
+    unit!("L"  ,"litre"           ,[0,3,0,0,0,0,0]  ,1e-3           ,true ),
+    unit!("min","minute"          ,[0,0,1,0,0,0,0]  ,60.0           ,false),
+    unit!("h"  ,"hour"            ,[0,0,1,0,0,0,0]  ,3600.0         ,false),
+    unit!("in" ,"inch"            ,[0,1,0,0,0,0,0]  ,0.0254         ,false),
+    unit!("ft" ,"foot"            ,[0,1,0,0,0,0,0]  ,0.3048         ,false),
+    unit!("mi" ,"mile"            ,[0,1,0,0,0,0,0]  ,1609.344       ,false),
+    unit!("lb" ,"pound"           ,[1,0,0,0,0,0,0]  ,0.45359237     ,false),
+    unit!("lbf","pound-force"     ,[1,1,-2,0,0,0,0] ,4.4482216152605,false),
+    unit!("bar","bar"             ,[1,-1,-2,0,0,0,0],1e5            ,false),
+    unit!("atm","atmosphere"      ,[1,-1,-2,0,0,0,0],101325.0       ,false),
+    unit!("psi","pound per sq. in",[1,-1,-2,0,0,0,0],6894.757293168 ,false),
+    unit!("cal","calorie"         ,[1,2,-2,0,0,0,0] ,4.184          ,true ),
+    unit!("eV" ,"electronvolt"    ,[1,2,-2,0,0,0,0] ,1.602176634e-19,true )
+];
+
+pub static PREFIXES: &[Prefix] = &[
+    Prefix { symbol: ""  , factor: 1.0  },
+    Prefix { symbol: "G" , factor: 1e9  },
+    Prefix { symbol: "M" , factor: 1e6  },
+    Prefix { symbol: "k" , factor: 1e3  },
+    Prefix { symbol: "c" , factor: 1e-2 },
+    Prefix { symbol: "m" , factor: 1e-3 },
+    Prefix { symbol: "µ" , factor: 1e-6 },
+    Prefix { symbol: "n" , factor: 1e-9 }
+];
+
+/// A catalog unit with a prefix, as indices into `UNITS` and `PREFIXES`.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct UnitRef {
+    pub unit: u8,
+    pub prefix: u8
+}
+
+impl UnitRef {
+    pub fn unit(self) -> Unit {
+        let def = &UNITS[self.unit as usize];
+        let prefix = &PREFIXES[self.prefix as usize];
+        Unit { dims: def.dims, factor: def.factor * prefix.factor, symbol: format!("{}{}",prefix.symbol,def.symbol) }
+    }
+}
+
+/// A magnitude in `unit`, falling back to a plain number when there are no
+/// dimensions left.
+fn quantity(magnitude: f64, unit: Unit) -> Value {
+    if unit.dims.is_zero() {
+        Value::Float(magnitude * unit.factor)
+    } else {
+        Value::Quantity(magnitude,unit)
+    }
+}
+
+/// A result in the coherent SI unit of `dims`.
+fn si(magnitude: f64, factor: f64, dims: Dims) -> Value {
+    quantity(magnitude * factor,Unit::si(dims))
+}
+
+fn split(v: &Value) -> (f64,Unit) {
+    match v {
+        Value::Quantity(q,u) => (*q,u.clone()),
+        v => (v.to_f64(),Unit::one())
+    }
+}
+
+/// Expresses `magnitude` of `from` in `to`.
+fn convert(magnitude: f64, from: &Unit, to: &Unit) -> Result<f64,CalcError> {
+    if from.dims != to.dims {
+        return Err(CalcError::IncompatibleUnits);
+    }
+    Ok(magnitude * from.factor / to.factor)
+}
+
+/// Runs `op` on arguments of which at least one carries a unit. Sums keep Y's
+/// unit; products are in Y's or X's unit when the other is a plain number and
+/// in SI base units otherwise.
+pub fn eval(op: Op, args: &[Value]) -> Result<Value,CalcError> {
+    let args = args.iter().map(split).collect::<Vec<_>>();
+    match (op,&args[..]) {
+        (Op::Arith(a @ (Arith::Add | Arith::Sub)),[(y,uy),(x,ux)]) => {
+            let x = convert(*x,ux,uy)?;
+            Ok(quantity(if a == Arith::Add { y + x } else { y - x },uy.clone()))
+        },
+        (Op::Arith(Arith::Mul),[(y,uy),(x,ux)]) if ux.dims.is_zero() => Ok(quantity(y * x * ux.factor,uy.clone())),
+        (Op::Arith(Arith::Mul),[(y,uy),(x,ux)]) if uy.dims.is_zero() => Ok(quantity(y * uy.factor * x,ux.clone())),
+        (Op::Arith(Arith::Mul),[(y,uy),(x,ux)]) => Ok(si(y * x,uy.factor * ux.factor,uy.dims.zip(ux.dims,i8::checked_add)?)),
+        (Op::Arith(Arith::Div),[_,(x,_)]) if *x == 0.0 => Err(CalcError::DivisionByZero),
+        (Op::Arith(Arith::Div),[(y,uy),(x,ux)]) if ux.dims.is_zero() => Ok(quantity(y / (x * ux.factor),uy.clone())),
+        (Op::Arith(Arith::Div),[(y,uy),(x,ux)]) => Ok(si(y / x,uy.factor / ux.factor,uy.dims.zip(ux.dims,i8::checked_sub)?)),
+        (Op::Exp2(Exp2::Pow),[(y,uy),(x,ux)]) if ux.dims.is_zero() && x.fract() == 0.0 && x.abs() <= 16.0 => {
+            let n = *x as i8;
+            Ok(si(y.powi(n as i32),uy.factor.powi(n as i32),uy.dims.scale(n)?))
+        },
+        (Op::Exp(Exp::Root2),[(x,ux)]) if ux.dims.0.iter().all(|e| e % 2 == 0) => {
+            Ok(si(x.sqrt(),ux.factor.sqrt(),Dims(ux.dims.0.map(|e| e / 2))))
+        },
+        _ => Err(CalcError::IncompatibleUnits)
+    }
+}
+
+pub fn eval_native(op: UnitOp, mut args: Vec<Value>) -> Result<Value,CalcError> {
+    match op {
+        UnitOp::To(r) => {
+            let (x,ux) = split(&args.pop().unwrap());
+            let to = r.unit();
+            match ux.dims.is_zero() {
+                true => Ok(quantity(x * ux.factor,to)),
+                false => Ok(quantity(convert(x,&ux,&to)?,to))
+            }
+        },
+        UnitOp::Convert => {
+            let (_,to) = split(&args.pop().unwrap());
+            let (y,from) = split(&args.pop().unwrap());
+            Ok(quantity(convert(y,&from,&to)?,to))
+        },
+        UnitOp::Strip => Ok(match args.pop().unwrap() {
+            Value::Quantity(q,_) => Value::Float(q),
+            v => v
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(symbol: &str, prefix: &str) -> UnitRef {
+        UnitRef {
+            unit: UNITS.iter().position(|u| u.symbol == symbol).unwrap() as u8,
+            prefix: PREFIXES.iter().position(|p| p.symbol == prefix).unwrap() as u8
+        }
+    }
+
+    fn magnitude(v: Value) -> f64 {
+        match v {
+            Value::Quantity(q,_) => q,
+            v => panic!("expected a quantity, got {v:?}")
+        }
+    }
+
+    #[test]
+    fn converts_between_units() {
+        let mile = Value::Quantity(1.0,unit("mi","").unit());
+        let km = magnitude(eval_native(UnitOp::To(unit("m","k")),vec![mile]).unwrap());
+        assert!((km - 1.609344).abs() < 1e-12);
+        let psi = Value::Quantity(1.0,unit("atm","").unit());
+        let to = Value::Quantity(1.0,unit("psi","").unit());
+        assert!((magnitude(eval_native(UnitOp::Convert,vec![psi,to]).unwrap()) - 14.695949).abs() < 1e-6);
+    }
+
+    #[test]
+    fn plain_numbers_take_the_unit() {
+        let v = eval_native(UnitOp::To(unit("ft","")),vec![Value::Float(3.0)]).unwrap();
+        assert_eq!(v,Value::Quantity(3.0,unit("ft","").unit()));
+    }
+
+    #[test]
+    fn dimensions_must_match() {
+        let metre = Value::Quantity(1.0,unit("m","").unit());
+        let second = Value::Quantity(1.0,unit("s","").unit());
+        assert_eq!(eval(Op::Arith(Arith::Add),&[metre.clone(),second.clone()]),Err(CalcError::IncompatibleUnits));
+        assert_eq!(eval_native(UnitOp::To(unit("s","")),vec![metre.clone()]),Err(CalcError::IncompatibleUnits));
+        let speed = eval(Op::Arith(Arith::Div),&[metre,second]).unwrap();
+        assert_eq!(speed,Value::Quantity(1.0,Unit::si(Dims([0,1,-1,0,0,0,0]))));
+    }
+
+    #[test]
+    fn units_cancel_to_plain_numbers() {
+        let km = Value::Quantity(2.0,unit("m","k").unit());
+        let m = Value::Quantity(500.0,unit("m","").unit());
+        match eval(Op::Arith(Arith::Div),&[km,m]) {
+            Ok(Value::Float(ratio)) => assert!((ratio - 4.0).abs() < 1e-12),
+            v => panic!("expected a plain number, got {v:?}")
+        }
+    }
+
+    #[test]
+    fn exponent_overflow_is_an_error() {
+        let m16 = eval(Op::Exp2(Exp2::Pow),&[Value::Quantity(1.0,unit("m","").unit()),Value::Float(16.0)]).unwrap();
+        let m128 = eval(Op::Exp2(Exp2::Pow),&[m16.clone(),Value::Float(8.0)]);
+        assert_eq!(m128,Err(CalcError::TooLarge));
+        let m112 = eval(Op::Exp2(Exp2::Pow),&[m16.clone(),Value::Float(7.0)]).unwrap();
+        assert_eq!(eval(Op::Arith(Arith::Mul),&[m112.clone(),m16.clone()]),Err(CalcError::TooLarge));
+        assert!(eval(Op::Arith(Arith::Mul),&[m112,Value::Quantity(1.0,unit("m","").unit())]).is_ok());
+        let per_m16 = eval(Op::Arith(Arith::Div),&[Value::Float(1.0),m16]).unwrap();
+        // i8 goes down to -128 but no further.
+        assert!(eval(Op::Exp2(Exp2::Pow),&[per_m16.clone(),Value::Float(8.0)]).is_ok());
+        assert_eq!(eval(Op::Exp2(Exp2::Pow),&[per_m16,Value::Float(9.0)]),Err(CalcError::TooLarge));
+    }
+}
@@ -0,0 +1,409 @@
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_complex::Complex64;
+use super::units::Unit;
+use super::linalg::Matrix;
+use super::CalcError;
+use num_traits::{ToPrimitive,Zero,One,Signed};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// A single stack entry.
+#[derive(Clone,PartialEq,Debug)]
+pub enum Value {
+    Float(f64),
+    Decimal(BigDecimal),
+    Rational(BigRational),
+    Complex(Complex64),
+    /// A machine word's value, kept within the range of the integer
+    /// backend's word size.
+    Integer(i128),
+    /// An exact integer too large for the backend's own type.
+    BigInt(BigInt),
+    /// A magnitude in a unit of measure.
+    Quantity(f64,Unit),
+    Vector(Vec<f64>),
+    Matrix(Matrix)
+}
+
+/// How fractions are written out.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum FractionStyle {
+    /// `4/3`
+    Improper,
+    /// `1 1/3`
+    Mixed
+}
+
+/// How complex numbers are written out.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum ComplexStyle {
+    /// `3+i4`
+    Rectangular,
+    /// `5∠0.927295`, angle in radians.
+    Polar
+}
+
+/// Radix integers are written in.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Base {
+    Bin,
+    Oct,
+    Dec,
+    Hex
+}
+
+impl Base {
+    pub fn radix(self) -> u32 {
+        match self {
+            Base::Bin => 2,
+            Base::Oct => 8,
+            Base::Dec => 10,
+            Base::Hex => 16
+        }
+    }
+}
+
+/// User preferences for turning values into text.
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct Format {
+    pub fractions: FractionStyle,
+    pub complex: ComplexStyle,
+    pub base: Base,
+    /// Width negative integers are shown in two's complement with, outside base ten.
+    pub word_bits: u32
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format { fractions: FractionStyle::Improper, complex: ComplexStyle::Rectangular, base: Base::Dec, word_bits: 64 }
+    }
+}
+
+impl Value {
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Float(f) => *f,
+            Value::Decimal(d) => d.to_f64().unwrap_or(f64::NAN),
+            Value::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+            Value::Complex(c) if c.im == 0.0 => c.re,
+            Value::Complex(_) => f64::NAN,
+            Value::Integer(i) => *i as f64,
+            Value::BigInt(n) => n.to_f64().unwrap_or(f64::NAN),
+            Value::Quantity(q,_) => *q,
+            Value::Vector(_) | Value::Matrix(_) => f64::NAN
+        }
+    }
+    /// The integer part, for values that have one that fits.
+    pub fn trunc_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            Value::BigInt(n) => n.to_i128(),
+            Value::Complex(_) | Value::Quantity(..) | Value::Vector(_) | Value::Matrix(_) => None,
+            Value::Rational(r) => r.trunc().to_integer().to_i128(),
+            v => v.to_decimal()?.with_scale(0).to_i128()
+        }
+    }
+    /// The value as an integer, if it is one.
+    pub fn to_integer(&self) -> Option<i128> {
+        match self {
+            Value::Float(f) if f.fract() != 0.0 => None,
+            Value::Decimal(d) if !d.is_integer() => None,
+            Value::Rational(r) if !r.is_integer() => None,
+            v => v.trunc_integer()
+        }
+    }
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Value::Complex(c) => *c,
+            v => Complex64::new(v.to_f64(),0.0)
+        }
+    }
+    pub fn is_nan(&self) -> bool {
+        match self {
+            Value::Float(f) => f.is_nan(),
+            Value::Complex(c) => c.is_nan(),
+            Value::Quantity(q,_) => q.is_nan(),
+            _ => false
+        }
+    }
+    /// Orders two real numbers, exactly when neither is a float. Complex
+    /// numbers, quantities, arrays and NaN have no place in the order.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        let real = |v: &Value| !matches!(v,Value::Complex(_) | Value::Quantity(..) | Value::Vector(_) | Value::Matrix(_));
+        if !real(self) || !real(other) {
+            return None;
+        }
+        match (self.to_rational(),other.to_rational()) {
+            (Some(a),Some(b)) => Some(a.cmp(&b)),
+            _ => self.to_f64().partial_cmp(&other.to_f64())
+        }
+    }
+    /// Magnitude, in the value's own type where it has one. Vectors and
+    /// matrices give their Euclidean (Frobenius) norm.
+    pub fn abs(&self) -> Value {
+        match self {
+            Value::Float(f) => Value::Float(f.abs()),
+            Value::Decimal(d) => Value::Decimal(d.abs()),
+            Value::Rational(r) => Value::Rational(r.abs()),
+            Value::Complex(c) => Value::Float(c.norm()),
+            Value::Integer(i) => Value::Integer(i.abs()),
+            Value::BigInt(n) => Value::BigInt(n.abs()),
+            Value::Quantity(q,u) => Value::Quantity(q.abs(),u.clone()),
+            Value::Vector(v) => Value::Float(v.iter().map(|x| x * x).sum::<f64>().sqrt()),
+            Value::Matrix(m) => Value::Float(m.data.iter().map(|x| x * x).sum::<f64>().sqrt())
+        }
+    }
+    /// `None` for infinities, NaN, complex numbers, quantities and arrays,
+    /// which decimals cannot hold. Floats go through their shortest representation, so `0.1`
+    /// becomes exactly `0.1`.
+    pub fn to_decimal(&self) -> Option<BigDecimal> {
+        match self {
+            Value::Float(f) if f.is_finite() => BigDecimal::from_str(&f.to_string()).ok(),
+            Value::Float(_) => None,
+            Value::Decimal(d) => Some(d.clone()),
+            Value::Rational(r) => Some(BigDecimal::new(r.numer().clone(),0) / BigDecimal::new(r.denom().clone(),0)),
+            Value::Complex(_) | Value::Quantity(..) | Value::Vector(_) | Value::Matrix(_) => None,
+            Value::Integer(i) => Some(BigDecimal::new(BigInt::from(*i),0)),
+            Value::BigInt(n) => Some(BigDecimal::new(n.clone(),0))
+        }
+    }
+    /// The exact rational this value stands for. Floats are never exact, so
+    /// they give `None`, as do decimals with too large a power of ten.
+    pub fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Value::Float(_) | Value::Complex(_) | Value::Quantity(..) | Value::Vector(_) | Value::Matrix(_) => None,
+            Value::Decimal(d) => decimal_to_rational(d).ok(),
+            Value::Rational(r) => Some(r.clone()),
+            Value::Integer(i) => Some(BigRational::from_integer(BigInt::from(*i))),
+            Value::BigInt(n) => Some(BigRational::from_integer(n.clone()))
+        }
+    }
+    /// The closest fraction with no more digits than the value can vouch for:
+    /// within half an ulp for floats and half a unit in the last digit for decimals.
+    pub fn approximate_rational(&self) -> Option<BigRational> {
+        let (exact,tolerance) = match self {
+            Value::Float(f) => {
+                let exact = BigRational::from_float(*f)?;
+                let tolerance = BigRational::from_float(f.abs() * f64::EPSILON / 2.0)?;
+                (exact,tolerance)
+            },
+            Value::Decimal(d) => {
+                let exact = decimal_to_rational(d).ok()?;
+                let half_unit = BigDecimal::new(BigInt::from(5),d.as_bigint_and_exponent().1 + 1);
+                (exact,decimal_to_rational(&half_unit).ok()?)
+            },
+            Value::Rational(r) => return Some(r.clone()),
+            Value::Complex(_) | Value::Quantity(..) | Value::Vector(_) | Value::Matrix(_) => return None,
+            Value::Integer(i) => return Some(BigRational::from_integer(BigInt::from(*i))),
+            Value::BigInt(n) => return Some(BigRational::from_integer(n.clone()))
+        };
+        Some(best_rational(&exact,&tolerance))
+    }
+    pub fn format(&self, format: &Format) -> String {
+        match self {
+            Value::Float(n) => rpncalc::format_num(*n).to_string(),
+            Value::Decimal(d) => d.normalized().to_string(),
+            Value::Rational(r) => format_fraction(r,format.fractions),
+            Value::Complex(c) => match format.complex {
+                ComplexStyle::Rectangular => format!("{}{}i{}",rpncalc::format_num(c.re),if c.im < 0.0 { '-' } else { '+' },rpncalc::format_num(c.im.abs())),
+                ComplexStyle::Polar => format!("{}∠{}",rpncalc::format_num(c.norm()),rpncalc::format_num(c.arg()))
+            },
+            Value::Integer(i) => format_integer(*i,format.base,format.word_bits),
+            Value::BigInt(n) => format_big_integer(n,format.base),
+            Value::Quantity(q,u) => format!("{} {}",rpncalc::format_num(*q),u.symbol),
+            Value::Vector(v) => format_row(v),
+            Value::Matrix(m) => {
+                let mut rows = (0..m.rows.min(MAX_ROWS)).map(|r| format_row(m.row(r))).collect::<Vec<_>>();
+                if m.rows > MAX_ROWS {
+                    rows.push(format!("… {}×{}",m.rows,m.cols));
+                }
+                format!("[{}]",rows.join("\n "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"{}",self.format(&Format::default()))
+    }
+}
+
+/// Reads the entry line's complex forms: `a+ib`, `a-ib` and `r∠θ`. A
+/// missing imaginary magnitude, as in `a+i`, means one.
+pub fn parse_complex(text: &str) -> Option<Complex64> {
+    let part = |s: &str| s.trim_end_matches('.').parse::<f64>().ok();
+    if let Some((r,theta)) = text.split_once('∠') {
+        return Some(Complex64::from_polar(part(r)?,part(theta)?));
+    }
+    let (re,im) = text.split_once('i')?;
+    let im = if im.is_empty() { 1.0 } else { part(im)? };
+    let (re,im) = match re.strip_suffix('+') {
+        Some(re) => (re,im),
+        None => (re.strip_suffix('-')?,-im)
+    };
+    let re = if re.is_empty() { 0.0 } else { part(re)? };
+    Some(Complex64::new(re,im))
+}
+
+/// Largest power of ten a decimal is scaled by to make it an integer or a
+/// fraction, about as large as the biggest big integer.
+const MAX_SCALE: i64 = 30_000;
+
+/// Fails for decimals like `1e-999999999`, whose power of ten would take
+/// more memory than any result is worth.
+pub fn check_scale(d: &BigDecimal) -> Result<(),CalcError> {
+    match d.as_bigint_and_exponent().1.abs() {
+        scale if scale > MAX_SCALE => Err(CalcError::TooLarge),
+        _ => Ok(())
+    }
+}
+
+pub fn decimal_to_rational(d: &BigDecimal) -> Result<BigRational,CalcError> {
+    check_scale(d)?;
+    let (mantissa,scale) = d.as_bigint_and_exponent();
+    Ok(if scale >= 0 {
+        BigRational::new(mantissa,BigInt::from(10).pow(scale as u32))
+    } else {
+        BigRational::from_integer(mantissa * BigInt::from(10).pow((-scale) as u32))
+    })
+}
+
+/// Walks the continued fraction of `x` until a convergent lands within `tolerance`.
+fn best_rational(x: &BigRational, tolerance: &BigRational) -> BigRational {
+    let (mut h_prev,mut h) = (BigInt::zero(),BigInt::one());
+    let (mut k_prev,mut k) = (BigInt::one(),BigInt::zero());
+    let mut rest = x.clone();
+    loop {
+        let a = rest.floor().to_integer();
+        let h_next = &a * &h + &h_prev;
+        let k_next = &a * &k + &k_prev;
+        h_prev = std::mem::replace(&mut h,h_next);
+        k_prev = std::mem::replace(&mut k,k_next);
+        let approx = BigRational::new(h.clone(),k.clone());
+        let frac = rest - BigRational::from_integer(a);
+        if frac.is_zero() || (&approx - x).abs() <= *tolerance {
+            return approx;
+        }
+        rest = frac.recip();
+    }
+}
+
+/// How much of an array the stack list shows; the inspector shows the rest.
+const MAX_ROWS: usize = 4;
+const MAX_COLS: usize = 6;
+
+fn format_row(xs: &[f64]) -> String {
+    let mut cells = xs.iter().take(MAX_COLS).map(|&x| rpncalc::format_num(x).to_string()).collect::<Vec<_>>();
+    if xs.len() > MAX_COLS {
+        cells.push("…".to_string());
+    }
+    format!("[{}]",cells.join(", "))
+}
+
+fn format_integer(i: i128, base: Base, word_bits: u32) -> String {
+    let bits = if i < 0 {
+        (i as u128) & (u128::MAX >> (128 - word_bits.clamp(1,128)))
+    } else {
+        i as u128
+    };
+    match base {
+        Base::Dec => i.to_string(),
+        Base::Hex => format!("0x{bits:X}"),
+        Base::Oct => format!("0o{bits:o}"),
+        Base::Bin => format!("0b{bits:b}")
+    }
+}
+
+/// Sign and magnitude; there is no word to take the two's complement in.
+fn format_big_integer(n: &BigInt, base: Base) -> String {
+    let sign = if n.is_negative() { "-" } else { "" };
+    let digits = n.magnitude().to_str_radix(base.radix());
+    match base {
+        Base::Dec => n.to_string(),
+        Base::Hex => format!("{sign}0x{}",digits.to_uppercase()),
+        Base::Oct => format!("{sign}0o{digits}"),
+        Base::Bin => format!("{sign}0b{digits}")
+    }
+}
+
+fn format_fraction(r: &BigRational, style: FractionStyle) -> String {
+    if r.is_integer() {
+        return r.numer().to_string();
+    }
+    match style {
+        FractionStyle::Improper => format!("{}/{}",r.numer(),r.denom()),
+        FractionStyle::Mixed => {
+            let whole = r.trunc().to_integer();
+            if whole.is_zero() {
+                return format!("{}/{}",r.numer(),r.denom());
+            }
+            let rest = (r - BigRational::from_integer(whole.clone())).abs();
+            format!("{} {}/{}",whole,rest.numer(),rest.denom())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str) -> BigDecimal {
+        BigDecimal::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn extreme_scales_are_refused() {
+        assert_eq!(decimal_to_rational(&decimal("1e-999999999")),Err(CalcError::TooLarge));
+        assert_eq!(decimal_to_rational(&decimal("1e999999999")),Err(CalcError::TooLarge));
+        assert_eq!(Value::Decimal(decimal("1e-999999999")).to_rational(),None);
+        assert!(decimal_to_rational(&decimal("1e-30000")).is_ok());
+    }
+    fn ratio(n: i64, d: i64) -> BigRational {
+        BigRational::new(BigInt::from(n),BigInt::from(d))
+    }
+
+    #[test]
+    fn decimals_convert_to_fractions_and_back() {
+        assert_eq!(decimal_to_rational(&decimal("1.25")),Ok(ratio(5,4)));
+        assert_eq!(decimal_to_rational(&decimal("-0.125")),Ok(ratio(-1,8)));
+        assert_eq!(decimal_to_rational(&decimal("12e3")),Ok(ratio(12_000,1)));
+        for text in ["0.1","3.14159","-2.5e-7","42"] {
+            let r = decimal_to_rational(&decimal(text)).unwrap();
+            assert_eq!(Value::Rational(r).to_decimal(),Some(decimal(text)),"{text}");
+        }
+    }
+
+    #[test]
+    fn floats_approximate_to_short_fractions() {
+        assert_eq!(Value::Float(0.1).approximate_rational(),Some(ratio(1,10)));
+        assert_eq!(Value::Float(1.0 / 3.0).approximate_rational(),Some(ratio(1,3)));
+        assert_eq!(Value::Float(-22.0 / 7.0).approximate_rational(),Some(ratio(-22,7)));
+        assert_eq!(Value::Float(0.0).approximate_rational(),Some(ratio(0,1)));
+        let pi = Value::Float(std::f64::consts::PI).approximate_rational().unwrap();
+        assert!((pi.to_f64().unwrap() - std::f64::consts::PI).abs() < 1e-15);
+        assert_eq!(Value::Complex(Complex64::new(1.0,1.0)).approximate_rational(),None);
+    }
+
+    #[test]
+    fn decimals_approximate_within_half_their_last_digit() {
+        assert_eq!(Value::Decimal(decimal("0.333")).approximate_rational(),Some(ratio(1,3)));
+        assert_eq!(Value::Decimal(decimal("0.334")).approximate_rational(),Some(ratio(167,500)));
+        let third = BigDecimal::from(1) / BigDecimal::from(3);
+        assert_eq!(Value::Decimal(third).approximate_rational(),Some(ratio(1,3)));
+        assert_eq!(Value::Decimal(decimal("0.142857142857")).approximate_rational(),Some(ratio(1,7)));
+    }
+
+    #[test]
+    fn fractions_format_improper_or_mixed() {
+        let improper = Format::default();
+        let mixed = Format { fractions: FractionStyle::Mixed, ..Format::default() };
+        for (r,as_improper,as_mixed) in [(ratio(7,3),"7/3","2 1/3"),(ratio(-7,3),"-7/3","-2 1/3"),(ratio(2,3),"2/3","2/3"),(ratio(-2,3),"-2/3","-2/3"),(ratio(8,2),"4","4")] {
+            assert_eq!(Value::Rational(r.clone()).format(&improper),as_improper);
+            assert_eq!(Value::Rational(r).format(&mixed),as_mixed);
+        }
+    }
+}
@@ -1,4 +1,3 @@
-#![feature(cell_update)]
 mod app;
 mod utils;
 mod calc_unit;
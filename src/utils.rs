@@ -1,35 +1,205 @@
-pub mod scope_channel {
-    use yew::html::Scope;
-    use std::rc::Rc;
-    use once_cell::unsync::OnceCell;
-    use super::Ignore;
+pub mod bus {
+    use yew::Callback;
+    use std::any::{Any,TypeId};
+    use std::cell::RefCell;
+    use std::collections::{HashMap,VecDeque};
+    use std::rc::{Rc,Weak};
 
-    pub struct Sender<T: yew::Component>(Rc<OnceCell<Scope<T>>>);
-    pub struct Receiver<T: yew::Component>(Rc<OnceCell<Scope<T>>>);
+    /// App-wide publish/subscribe channel.
+    ///
+    /// Every event type is its own topic. Events published to a topic nobody
+    /// listens to yet are queued and handed to its first subscriber, so
+    /// components can mount in any order without losing messages.
+    /// Nothing here touches the DOM, which keeps it usable from native code.
+    #[derive(Default)]
+    pub struct Bus(Rc<RefCell<Topics>>);
 
-    impl<T: yew::Component> Sender<T> {
-        pub fn send(&self,val: Scope<T>) {
-            (self.0).set(val).ignore();
+    #[derive(Default)]
+    struct Topics {
+        next_id: usize,
+        topics: HashMap<TypeId,Box<dyn Any>>
+    }
+
+    struct Topic<E> {
+        subscribers: Vec<(usize,Callback<E>)>,
+        pending: VecDeque<E>
+    }
+
+    impl<E> Default for Topic<E> {
+        fn default() -> Self {
+            Topic { subscribers: vec![], pending: VecDeque::new() }
+        }
+    }
+
+    impl Topics {
+        fn topic<E: 'static>(&mut self) -> &mut Topic<E> {
+            self.topics.entry(TypeId::of::<E>())
+                .or_insert_with(|| Box::new(Topic::<E>::default()))
+                .downcast_mut()
+                .expect("bus topics are keyed by their event type")
+        }
+        fn unsubscribe<E: 'static>(&mut self, id: usize) {
+            self.topic::<E>().subscribers.retain(|(i,_)| *i != id);
+        }
+    }
+
+    impl Bus {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        /// Delivers `event` to every current subscriber of its type, or queues
+        /// it until one shows up.
+        pub fn publish<E: Clone + 'static>(&self, event: E) {
+            let subscribers = {
+                let mut topics = self.0.borrow_mut();
+                let topic = topics.topic::<E>();
+                if topic.subscribers.is_empty() {
+                    topic.pending.push_back(event);
+                    return;
+                }
+                topic.subscribers.iter().map(|(_,cb)| cb.clone()).collect::<Vec<_>>()
+            };
+            for cb in subscribers {
+                cb.emit(event.clone());
+            }
+        }
+        /// Listens for events of type `E` until the returned [`Subscription`] is dropped.
+        pub fn subscribe<E: Clone + 'static>(&self, cb: Callback<E>) -> Subscription {
+            let (id,pending) = {
+                let mut topics = self.0.borrow_mut();
+                let id = topics.next_id;
+                topics.next_id += 1;
+                let topic = topics.topic::<E>();
+                topic.subscribers.push((id,cb.clone()));
+                (id,std::mem::take(&mut topic.pending))
+            };
+            for event in pending {
+                cb.emit(event);
+            }
+            Subscription { topics: Rc::downgrade(&self.0), id, unsubscribe: Topics::unsubscribe::<E> }
+        }
+    }
+
+    impl Clone for Bus {
+        fn clone(&self) -> Self {
+            Bus(Rc::clone(&self.0))
         }
     }
 
-    impl<T: yew::Component> Receiver<T> {
-        pub fn recv(&self) -> &Scope<T> {
-            (self.0).get().unwrap()
+    impl std::cmp::PartialEq for Bus {
+        fn eq(&self, other: &Self) -> bool {
+            Rc::ptr_eq(&self.0,&other.0)
         }
     }
 
-    impl<T: yew::Component> std::cmp::PartialEq for Sender<T> {
-        fn eq(&self, _other: &Self) -> bool { true }
+    #[must_use = "the subscription ends as soon as this is dropped"]
+    pub struct Subscription {
+        topics: Weak<RefCell<Topics>>,
+        id: usize,
+        unsubscribe: fn(&mut Topics,usize)
     }
 
-    impl<T: yew::Component> std::cmp::PartialEq for Receiver<T> {
-        fn eq(&self, _other: &Self) -> bool { true }
+    impl Drop for Subscription {
+        fn drop(&mut self) {
+            if let Some(topics) = self.topics.upgrade() {
+                (self.unsubscribe)(&mut topics.borrow_mut(),self.id);
+            }
+        }
     }
 
-    pub fn scope_channel<T: yew::Component>() -> (Sender<T>,Receiver<T>) {
-        let inner = Rc::new(OnceCell::new());
-        (Sender(inner.clone()),Receiver(inner))
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[derive(Clone,PartialEq,Debug)]
+        struct Ping(u32);
+        #[derive(Clone,PartialEq,Debug)]
+        struct Pong(u32);
+
+        /// A callback that records every event it is given.
+        fn recorder<E: Clone + 'static>() -> (Rc<RefCell<Vec<E>>>,Callback<E>) {
+            let seen = Rc::new(RefCell::new(vec![]));
+            let sink = Rc::clone(&seen);
+            (seen,Callback::from(move |e: E| sink.borrow_mut().push(e)))
+        }
+
+        #[test]
+        fn publish_reaches_every_subscriber() {
+            let bus = Bus::new();
+            let (a,cb_a) = recorder::<Ping>();
+            let (b,cb_b) = recorder::<Ping>();
+            let _sa = bus.subscribe(cb_a);
+            let _sb = bus.subscribe(cb_b);
+            bus.publish(Ping(1));
+            assert_eq!(*a.borrow(),vec![Ping(1)]);
+            assert_eq!(*b.borrow(),vec![Ping(1)]);
+        }
+
+        #[test]
+        fn topics_are_kept_apart() {
+            let bus = Bus::new();
+            let (pings,cb) = recorder::<Ping>();
+            let _s = bus.subscribe(cb);
+            bus.publish(Pong(1));
+            assert!(pings.borrow().is_empty());
+        }
+
+        #[test]
+        fn pending_events_go_to_the_first_subscriber() {
+            let bus = Bus::new();
+            bus.publish(Ping(1));
+            bus.publish(Ping(2));
+            let (first,cb) = recorder::<Ping>();
+            let _s1 = bus.subscribe(cb);
+            assert_eq!(*first.borrow(),vec![Ping(1),Ping(2)]);
+            let (second,cb) = recorder::<Ping>();
+            let _s2 = bus.subscribe(cb);
+            assert!(second.borrow().is_empty());
+        }
+
+        #[test]
+        fn dropping_the_subscription_unsubscribes() {
+            let bus = Bus::new();
+            let (seen,cb) = recorder::<Ping>();
+            let (other,other_cb) = recorder::<Ping>();
+            let s = bus.subscribe(cb);
+            let _keep = bus.subscribe(other_cb);
+            bus.publish(Ping(1));
+            drop(s);
+            bus.publish(Ping(2));
+            assert_eq!(*seen.borrow(),vec![Ping(1)]);
+            assert_eq!(*other.borrow(),vec![Ping(1),Ping(2)]);
+        }
+
+        #[test]
+        fn subscriptions_outliving_the_bus_drop_quietly() {
+            let bus = Bus::new();
+            let (_,cb) = recorder::<Ping>();
+            let s = bus.subscribe(cb);
+            drop(bus);
+            drop(s);
+        }
+
+        #[test]
+        fn subscribers_may_publish_and_subscribe() {
+            let bus = Bus::new();
+            let (pongs,pong_cb) = recorder::<Pong>();
+            let _pongs = bus.subscribe(pong_cb);
+            let inner = bus.clone();
+            let late = Rc::new(RefCell::new(vec![]));
+            let late_sink = Rc::clone(&late);
+            let _pings = bus.subscribe(Callback::from(move |Ping(n)| {
+                inner.publish(Pong(n));
+                // Echo the ping on its own topic once, without looping.
+                if n == 1 {
+                    inner.publish(Ping(2));
+                }
+                late_sink.borrow_mut().push(inner.subscribe(Callback::from(|_: Pong| ())));
+            }));
+            bus.publish(Ping(1));
+            assert_eq!(*pongs.borrow(),vec![Pong(1),Pong(2)]);
+            assert_eq!(late.borrow().len(),2);
+        }
     }
 }
 
@@ -37,4 +207,4 @@ pub trait Ignore: Sized {
     fn ignore(self) {}
 }
 
-impl<T: Sized> Ignore for T {}
\ No newline at end of file
+impl<T: Sized> Ignore for T {}